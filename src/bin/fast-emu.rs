@@ -0,0 +1,172 @@
+//! fast-emu - Emulates a FAST controller on a pair of pseudo-terminals
+//!
+//! Reads a machine description, opens a PTY for each of the IO/NET and EXP busses, and
+//! prints their paths so the `Neutron` plugin can connect to them as if they were real
+//! hardware. Controllers without an EXP bus only get the IO/NET PTY. LED frames and driver
+//! commands are printed as they arrive, and typing a switch name or number toggles that
+//! switch.
+//!
+//! Usage: `cargo run --bin fast-emu -- machine.txt`
+
+#[cfg(unix)]
+fn main() {
+    if let Err(e) = emulator::run() {
+        eprintln!("fast-emu: {e}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("fast-emu: pseudo-terminals are only available on Linux and macOS");
+    std::process::exit(1);
+}
+
+#[cfg(unix)]
+mod emulator {
+    use bevy::color::Srgba;
+    use bevy_pin::fast::{
+        FastExpansionDevice, IoCommand, MachineDescription, SimulatedNeutron, SimulatedPort,
+    };
+    use serialport::{SerialPort, TTYPort};
+    use std::{
+        collections::HashMap,
+        env, fs,
+        io::{self, BufRead, Read, Write},
+        thread,
+        time::Duration,
+    };
+
+    /// How often received LED frames and driver commands are printed
+    const PRINT_INTERVAL: Duration = Duration::from_millis(33);
+
+    pub fn run() -> Result<(), String> {
+        let path = env::args()
+            .nth(1)
+            .ok_or("Usage: fast-emu <machine description>")?;
+        let machine = fs::read_to_string(&path)
+            .map_err(|e| format!("Unable to read {path}: {e}"))?
+            .parse::<MachineDescription>()?;
+        let simulator = SimulatedNeutron::new(machine.switch_count());
+        simulator.set_controller(machine.controller);
+        simulator.set_io_boards(machine.io_boards.clone());
+        for led_port in &machine.led_ports {
+            simulator.add_expansion_board(led_port.expansion_address);
+        }
+
+        // The other end of each pair has to stay open, or the master reads fail once the
+        // game closes its port
+        let (io_master, io_slave) = TTYPort::pair().map_err(|e| e.to_string())?;
+        println!("Controller:  {}", machine.controller);
+        println!("IO/NET port: {}", io_slave.name().unwrap_or_default());
+        bridge(io_master, simulator.io_port());
+        let _exp_slave = match machine.controller.has_exp_bus() {
+            true => {
+                let (exp_master, exp_slave) = TTYPort::pair().map_err(|e| e.to_string())?;
+                println!("EXP port:    {}", exp_slave.name().unwrap_or_default());
+                bridge(exp_master, simulator.exp_port());
+                Some(exp_slave)
+            }
+            false => None,
+        };
+        read_switches(simulator.clone(), machine.clone());
+
+        let mut frames = HashMap::new();
+        loop {
+            print_driver_commands(&simulator, &machine);
+            print_led_frames(&simulator, &machine, &mut frames);
+            thread::sleep(PRINT_INTERVAL);
+        }
+    }
+
+    /// Pass data back and forth between a PTY and the simulator
+    fn bridge(mut master: TTYPort, mut port: SimulatedPort) {
+        let _ = master.set_timeout(Duration::from_millis(5));
+        thread::spawn(move || {
+            let mut buffer = [0; 1024];
+            loop {
+                match master.read(&mut buffer) {
+                    Ok(count) => {
+                        let _ = port.write_all(&buffer[..count]);
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+                    Err(e) => {
+                        eprintln!("fast-emu: {e}");
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                }
+                if let Ok(count) = port.read(&mut buffer) {
+                    let _ = master.write_all(&buffer[..count]);
+                }
+            }
+        });
+    }
+
+    /// Toggle a switch for each name or number typed on stdin
+    fn read_switches(simulator: SimulatedNeutron, machine: MachineDescription) {
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                let name = line.trim();
+                if name.is_empty() {
+                    continue;
+                }
+                let Some(id) = machine.switch(name) else {
+                    println!("Unknown switch {name}");
+                    continue;
+                };
+                if simulator.switch_closed(id) {
+                    simulator.open_switch(id);
+                    println!("Switch {name} ({id}) opened");
+                } else {
+                    simulator.close_switch(id);
+                    println!("Switch {name} ({id}) closed");
+                }
+            }
+        });
+    }
+
+    fn print_driver_commands(simulator: &SimulatedNeutron, machine: &MachineDescription) {
+        for command in simulator.take_io_commands() {
+            let driver = match command {
+                IoCommand::DriverConfig(config) => config.driver,
+                IoCommand::DriverTrigger { driver, .. } => driver,
+                _ => continue,
+            };
+            let name = machine
+                .drivers
+                .iter()
+                .find(|(number, _)| *number == driver)
+                .map(|(_, name)| name.as_str())
+                .unwrap_or("driver");
+            println!("{name} ({driver}): {command}");
+        }
+    }
+
+    /// Print every LED port whose colors changed since it was last printed
+    fn print_led_frames(
+        simulator: &SimulatedNeutron,
+        machine: &MachineDescription,
+        frames: &mut HashMap<(&'static str, u8), String>,
+    ) {
+        for led_port in &machine.led_ports {
+            let frame = (0..led_port.count)
+                .map(|index| {
+                    let device = FastExpansionDevice {
+                        expansion_address: led_port.expansion_address,
+                        port: led_port.port,
+                        index,
+                    };
+                    let color = simulator.led(&device).unwrap_or(Srgba::NONE);
+                    color.to_hex()[1..7].to_string()
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            let key = (led_port.expansion_address, led_port.port);
+            if frames.get(&key) != Some(&frame) {
+                println!("LEDs {}-{}: {frame}", key.0, key.1);
+                frames.insert(key, frame);
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use bevy::prelude::*;
+use std::sync::Arc;
+
+use super::ExpansionBoard;
+
+/// The order an LED chip expects its color channels in
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelOrder {
+    #[default]
+    Rgb,
+    Grb,
+    Bgr,
+}
+
+impl ChannelOrder {
+    fn arrange(&self, [red, green, blue]: [u8; 3]) -> [u8; 3] {
+        match self {
+            ChannelOrder::Rgb => [red, green, blue],
+            ChannelOrder::Grb => [green, red, blue],
+            ChannelOrder::Bgr => [blue, green, red],
+        }
+    }
+}
+
+/// The kind of LED chip, by the channels it takes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LedType {
+    #[default]
+    Rgb,
+    /// e.g. SK6812 RGBW, which takes a fourth byte for its white channel
+    Rgbw,
+}
+
+impl LedType {
+    pub fn channel_count(&self) -> usize {
+        match self {
+            LedType::Rgb => 3,
+            LedType::Rgbw => 4,
+        }
+    }
+
+    /// The bytes to send for a calibrated color. RGBW chips show the white shared by every
+    /// channel on their white channel instead.
+    pub fn encode(&self, rgb: [u8; 3]) -> Vec<u8> {
+        match self {
+            LedType::Rgb => rgb.to_vec(),
+            LedType::Rgbw => {
+                let white = rgb.into_iter().min().unwrap_or_default();
+                let mut bytes = rgb.map(|channel| channel - white).to_vec();
+                bytes.push(white);
+                bytes
+            }
+        }
+    }
+}
+
+/// Scales each channel at full brightness, e.g. to take the blue tint out of an LED's white
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WhiteBalance {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+}
+
+impl Default for WhiteBalance {
+    fn default() -> Self {
+        Self {
+            red: 1.,
+            green: 1.,
+            blue: 1.,
+        }
+    }
+}
+
+/// LED type, color order and white balance of every LED on an expansion board port
+#[derive(Debug, Default, Clone)]
+pub struct PortCalibration {
+    pub board: ExpansionBoard,
+    pub port: u8,
+    pub led_type: LedType,
+    pub channel_order: ChannelOrder,
+    pub white_balance: WhiteBalance,
+}
+
+/// How colors are corrected before being sent to an LED
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LedCalibration {
+    /// Exponent applied to each channel, e.g. 2.2 so the PWM of the LED matches sRGB
+    pub gamma: f32,
+    pub channel_order: ChannelOrder,
+    pub white_balance: WhiteBalance,
+}
+
+impl Default for LedCalibration {
+    fn default() -> Self {
+        Self {
+            gamma: 1.,
+            channel_order: ChannelOrder::default(),
+            white_balance: WhiteBalance::default(),
+        }
+    }
+}
+
+/// CalibrationTable - Precomputed output levels for an `LedCalibration`
+///
+/// LEDs with the same calibration share a table, so an LED looks the same whichever
+/// update it was sent in.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct CalibrationTable {
+    channel_order: ChannelOrder,
+    /// Output level of each input level, for red, green and blue
+    levels: Arc<[[u8; 256]; 3]>,
+}
+
+impl CalibrationTable {
+    pub fn new(calibration: &LedCalibration) -> Self {
+        let WhiteBalance { red, green, blue } = calibration.white_balance;
+        let mut levels = [[0; 256]; 3];
+        for (channel, scale) in levels.iter_mut().zip([red, green, blue]) {
+            for (input, output) in channel.iter_mut().enumerate() {
+                let level = (input as f32 / 255.).powf(calibration.gamma) * scale;
+                *output = (level * 255.).round().clamp(0., 255.) as u8;
+            }
+        }
+        Self {
+            channel_order: calibration.channel_order,
+            levels: Arc::new(levels),
+        }
+    }
+
+    /// The bytes to send for a color, in the LED's channel order. `brightness` scales every
+    /// channel before it is corrected.
+    pub fn apply(&self, color: Srgba, brightness: f32) -> [u8; 3] {
+        let level = |value: f32| (value * brightness * 255.).round().clamp(0., 255.) as usize;
+        self.channel_order.arrange([
+            self.levels[0][level(color.red)],
+            self.levels[1][level(color.green)],
+            self.levels[2][level(color.blue)],
+        ])
+    }
+}
+
+impl Default for CalibrationTable {
+    fn default() -> Self {
+        Self::new(&LedCalibration::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reorders_channels() {
+        let color = Srgba::rgb_u8(255, 128, 0);
+        let table = |channel_order| {
+            CalibrationTable::new(&LedCalibration {
+                channel_order,
+                ..Default::default()
+            })
+        };
+        assert_eq!(table(ChannelOrder::Rgb).apply(color, 1.), [255, 128, 0]);
+        assert_eq!(table(ChannelOrder::Grb).apply(color, 1.), [128, 255, 0]);
+        assert_eq!(table(ChannelOrder::Bgr).apply(color, 1.), [0, 128, 255]);
+    }
+
+    #[test]
+    fn it_applies_gamma_then_white_balance() {
+        let table = CalibrationTable::new(&LedCalibration {
+            gamma: 2.,
+            white_balance: WhiteBalance {
+                red: 1.,
+                green: 0.5,
+                blue: 0.8,
+            },
+            ..Default::default()
+        });
+        assert_eq!(table.apply(Srgba::WHITE, 1.), [255, 128, 204]);
+        assert_eq!(table.apply(Srgba::rgb_u8(128, 0, 0), 1.), [64, 0, 0]);
+        assert_eq!(table.apply(Srgba::BLACK, 1.), [0, 0, 0]);
+    }
+
+    #[test]
+    fn it_moves_shared_white_to_the_white_channel() {
+        assert_eq!(LedType::Rgb.encode([255, 200, 100]), vec![255, 200, 100]);
+        assert_eq!(
+            LedType::Rgbw.encode([255, 200, 100]),
+            vec![155, 100, 0, 100]
+        );
+        assert_eq!(LedType::Rgbw.encode([0, 128, 255]), vec![0, 128, 255, 0]);
+    }
+
+    #[test]
+    fn it_scales_brightness_before_correcting() {
+        let table = CalibrationTable::default();
+        assert_eq!(table.apply(Srgba::WHITE, 0.5), [128, 128, 128]);
+    }
+}
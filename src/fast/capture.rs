@@ -0,0 +1,225 @@
+use bevy::prelude::*;
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{framing::LineBuffer, transport::Transport};
+
+/// Which FAST bus a frame was sent on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CaptureBus {
+    IoNet,
+    Exp,
+}
+
+/// Whether a frame was sent to the hardware or received from it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CaptureDirection {
+    Sent,
+    Received,
+}
+
+/// A single message on one of the busses, stored in a capture file as
+/// `<ms since the unix epoch> <IO|EXP> <direction> <message>`, where the direction is
+/// `>` for sent to the hardware and `<` for received from it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedFrame {
+    pub time: Duration,
+    pub bus: CaptureBus,
+    pub direction: CaptureDirection,
+    pub message: String,
+}
+
+impl fmt::Display for CapturedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bus = match self.bus {
+            CaptureBus::IoNet => "IO",
+            CaptureBus::Exp => "EXP",
+        };
+        let direction = match self.direction {
+            CaptureDirection::Sent => ">",
+            CaptureDirection::Received => "<",
+        };
+        write!(
+            f,
+            "{} {bus} {direction} {}",
+            self.time.as_millis(),
+            self.message
+        )
+    }
+}
+
+impl FromStr for CapturedFrame {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(4, ' ');
+        let (Some(time), Some(bus), Some(direction), Some(message)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(s.to_string());
+        };
+        Ok(CapturedFrame {
+            time: Duration::from_millis(time.parse().map_err(|_| s.to_string())?),
+            bus: match bus {
+                "IO" => CaptureBus::IoNet,
+                "EXP" => CaptureBus::Exp,
+                _ => return Err(s.to_string()),
+            },
+            direction: match direction {
+                ">" => CaptureDirection::Sent,
+                "<" => CaptureDirection::Received,
+                _ => return Err(s.to_string()),
+            },
+            message: message.to_string(),
+        })
+    }
+}
+
+/// A capture file, shared by both ports
+#[derive(Debug, Clone)]
+pub struct Capture {
+    file: Arc<Mutex<File>>,
+}
+
+impl Capture {
+    /// Start a new capture, replacing any previous one at the path
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            file: Arc::new(Mutex::new(File::create(path)?)),
+        })
+    }
+
+    /// Continue an existing capture, e.g. after reconnecting
+    pub fn append(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Wrap a port so everything sent and received on it is recorded
+    pub fn wrap(&self, bus: CaptureBus, inner: Box<dyn Transport>) -> CaptureTransport {
+        CaptureTransport {
+            inner,
+            bus,
+            capture: self.clone(),
+            sent: LineBuffer::default(),
+            received: LineBuffer::default(),
+        }
+    }
+
+    fn record(&self, bus: CaptureBus, direction: CaptureDirection, message: String) {
+        let frame = CapturedFrame {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            bus,
+            direction,
+            message,
+        };
+        if let Err(e) = writeln!(self.file.lock().unwrap(), "{frame}") {
+            error!("Unable to write capture: {e}");
+        }
+    }
+}
+
+/// A port which records its traffic to a `Capture`
+#[derive(Debug)]
+pub struct CaptureTransport {
+    inner: Box<dyn Transport>,
+    bus: CaptureBus,
+    capture: Capture,
+    sent: LineBuffer,
+    received: LineBuffer,
+}
+
+impl Read for CaptureTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        for message in self.received.push(&String::from_utf8_lossy(&buf[..count])) {
+            self.capture
+                .record(self.bus, CaptureDirection::Received, message);
+        }
+        Ok(count)
+    }
+}
+
+impl Write for CaptureTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        for message in self.sent.push(&String::from_utf8_lossy(&buf[..count])) {
+            self.capture
+                .record(self.bus, CaptureDirection::Sent, message);
+        }
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Transport for CaptureTransport {
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fast::mock_port::MockPort;
+    use std::{env, fs, process};
+
+    #[test]
+    fn it_round_trips_frames() {
+        let frame = CapturedFrame {
+            time: Duration::from_millis(1_700_000_000_123),
+            bus: CaptureBus::Exp,
+            direction: CaptureDirection::Sent,
+            message: "RS@480:1ff8000".to_string(),
+        };
+        assert_eq!(frame.to_string(), "1700000000123 EXP > RS@480:1ff8000");
+        assert_eq!(frame.to_string().parse(), Ok(frame));
+        assert!("12 IO ? ID:".parse::<CapturedFrame>().is_err());
+    }
+
+    #[test]
+    fn it_records_both_directions() {
+        let path = env::temp_dir().join(format!("fast-capture-{}.txt", process::id()));
+        let capture = Capture::create(&path).unwrap();
+        let port = MockPort::default();
+        let mut transport = capture.wrap(CaptureBus::IoNet, Box::new(port.clone()));
+
+        transport.write_all(b"ID:\r").unwrap();
+        port.respond("ID:NET FP-CPU-2000 02.13\r");
+        let mut response = String::new();
+        let _ = transport.read_to_string(&mut response);
+
+        let frames = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| line.parse::<CapturedFrame>().unwrap())
+            .map(|frame| (frame.bus, frame.direction, frame.message))
+            .collect::<Vec<_>>();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            frames,
+            vec![
+                (CaptureBus::IoNet, CaptureDirection::Sent, "ID:".to_string()),
+                (
+                    CaptureBus::IoNet,
+                    CaptureDirection::Received,
+                    "ID:NET FP-CPU-2000 02.13".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,472 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+/// A command sent to the FAST IO/NET port
+/// See: https://fastpinball.com/programming/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IoCommand {
+    /// `ID:` - Identify the board
+    Id,
+    /// `CH:` - Configure which hardware platform the controller is running as
+    ConfigureHardware { hardware: u16, options: u8 },
+    /// `SA:` - Request the state of every switch
+    SwitchStates,
+    /// `SL:` - Configure how a switch is reported
+    SwitchConfig(SwitchConfig),
+    /// `DL:` - Configure a driver
+    DriverConfig(DriverConfig),
+    /// `TL:` - Manually control a driver
+    DriverTrigger { driver: u16, control: DriverControl },
+    /// `WD:` - Set the watchdog timeout, or query the time remaining when `None`
+    Watchdog(Option<Duration>),
+    /// `NN:` - Request information about a node on the IO loop
+    NodeInfo { node: u8 },
+}
+
+/// How a switch is reported to the host
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SwitchMode {
+    /// Switch changes are not reported
+    Disabled,
+    /// Closed when the circuit is closed
+    #[default]
+    Normal,
+    /// Closed when the circuit is open, e.g. normally-closed optos
+    Inverted,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SwitchConfig {
+    pub switch: u16,
+    pub mode: SwitchMode,
+    /// Time in ms the switch must be closed before it is reported closed
+    pub debounce_close: u8,
+    /// Time in ms the switch must be open before it is reported open
+    pub debounce_open: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DriverConfig {
+    pub driver: u16,
+    pub trigger: DriverTrigger,
+    /// Switch which triggers the driver when the trigger is not manual
+    pub switch: u16,
+    pub mode: DriverMode,
+}
+
+/// Trigger flags for a driver
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DriverTrigger {
+    pub enabled: bool,
+    /// Trigger on switch open instead of switch closed
+    pub invert_switch: bool,
+    /// Only fire from `TL` commands, ignoring the switch
+    pub manual: bool,
+}
+
+impl DriverTrigger {
+    const ENABLED: u8 = 0x01;
+    const INVERT_SWITCH: u8 = 0x10;
+    const MANUAL: u8 = 0x80;
+
+    pub fn bits(&self) -> u8 {
+        let mut bits = 0;
+        if self.enabled {
+            bits |= Self::ENABLED;
+        }
+        if self.invert_switch {
+            bits |= Self::INVERT_SWITCH;
+        }
+        if self.manual {
+            bits |= Self::MANUAL;
+        }
+        bits
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            enabled: bits & Self::ENABLED != 0,
+            invert_switch: bits & Self::INVERT_SWITCH != 0,
+            manual: bits & Self::MANUAL != 0,
+        }
+    }
+}
+
+/// Driver modes and their parameters. Power is given as a PWM duty from `0` (off) to `255` (full)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriverMode {
+    /// `10` - Pulse, then optionally a second lower power pulse
+    Pulse {
+        pulse_ms: u8,
+        pulse_power: u8,
+        second_ms: u8,
+        second_power: u8,
+        recycle_ms: u8,
+    },
+    /// `18` - Pulse, then hold for as long as the driver is on
+    PulseHold {
+        pulse_ms: u8,
+        pulse_power: u8,
+        hold_power: u8,
+        recycle_ms: u8,
+    },
+    /// `70` - Pulse, then hold for a fixed time
+    TimedHold {
+        pulse_ms: u8,
+        pulse_power: u8,
+        hold_power: u8,
+        hold_ms: u16,
+        recycle_ms: u8,
+    },
+    /// `75` - Pulse until the cancel switch (e.g. flipper EOS) closes, then hold
+    PulseHoldCancel {
+        pulse_ms: u8,
+        pulse_power: u8,
+        hold_power: u8,
+        cancel_switch: u16,
+        recycle_ms: u8,
+    },
+}
+
+/// Manual driver control given with `TL`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriverControl {
+    /// Return control to the driver's switch
+    Automatic,
+    /// Turn the driver on as configured
+    On,
+    /// Turn the driver off
+    Off,
+    /// Fire the driver once as configured
+    Tap,
+}
+
+impl DriverControl {
+    fn code(&self) -> u8 {
+        match self {
+            Self::Automatic => 0,
+            Self::On => 1,
+            Self::Off => 2,
+            Self::Tap => 3,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::Automatic),
+            1 => Some(Self::On),
+            2 => Some(Self::Off),
+            3 => Some(Self::Tap),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for IoCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Id => write!(f, "ID:"),
+            Self::ConfigureHardware { hardware, options } => {
+                write!(f, "CH:{:X},{:X}", hardware, options)
+            }
+            Self::SwitchStates => write!(f, "SA:"),
+            Self::SwitchConfig(config) => {
+                let mode = match config.mode {
+                    SwitchMode::Disabled => 0,
+                    SwitchMode::Normal => 1,
+                    SwitchMode::Inverted => 2,
+                };
+                write!(
+                    f,
+                    "SL:{:02X},{},{:02X},{:02X}",
+                    config.switch, mode, config.debounce_close, config.debounce_open
+                )
+            }
+            Self::DriverConfig(config) => {
+                write!(
+                    f,
+                    "DL:{:02X},{:02X},{:02X},",
+                    config.driver,
+                    config.trigger.bits(),
+                    config.switch
+                )?;
+                match config.mode {
+                    DriverMode::Pulse {
+                        pulse_ms,
+                        pulse_power,
+                        second_ms,
+                        second_power,
+                        recycle_ms,
+                    } => write!(
+                        f,
+                        "10,{:02X},{:02X},{:02X},{:02X},{:02X}",
+                        pulse_ms, pulse_power, second_ms, second_power, recycle_ms
+                    ),
+                    DriverMode::PulseHold {
+                        pulse_ms,
+                        pulse_power,
+                        hold_power,
+                        recycle_ms,
+                    } => write!(
+                        f,
+                        "18,{:02X},{:02X},{:02X},{:02X},00",
+                        pulse_ms, pulse_power, hold_power, recycle_ms
+                    ),
+                    DriverMode::TimedHold {
+                        pulse_ms,
+                        pulse_power,
+                        hold_power,
+                        hold_ms,
+                        recycle_ms,
+                    } => write!(
+                        f,
+                        "70,{:02X},{:02X},{:02X},{:04X},{:02X}",
+                        pulse_ms, pulse_power, hold_power, hold_ms, recycle_ms
+                    ),
+                    DriverMode::PulseHoldCancel {
+                        pulse_ms,
+                        pulse_power,
+                        hold_power,
+                        cancel_switch,
+                        recycle_ms,
+                    } => write!(
+                        f,
+                        "75,{:02X},{:02X},{:02X},{:02X},{:02X}",
+                        pulse_ms, pulse_power, hold_power, cancel_switch, recycle_ms
+                    ),
+                }
+            }
+            Self::DriverTrigger { driver, control } => {
+                write!(f, "TL:{:02X},{}", driver, control.code())
+            }
+            Self::Watchdog(Some(timeout)) => write!(f, "WD:{:X}", timeout.as_millis()),
+            Self::Watchdog(None) => write!(f, "WD:"),
+            Self::NodeInfo { node } => write!(f, "NN:{:02X}", node),
+        }
+    }
+}
+
+impl FromStr for IoCommand {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let Some((cmd, all_args)) = input.trim_end_matches('\r').split_once(':') else {
+            return Err("Invalid command syntax: Missing command.".to_string());
+        };
+        let args = parse_hex_args(all_args).ok_or_else(|| input.to_string())?;
+        let arg = |i: usize| args.get(i).copied().ok_or_else(|| input.to_string());
+        // Values which don't fit their field are rejected rather than truncated
+        let byte = |i: usize| u8::try_from(arg(i)?).map_err(|_| input.to_string());
+        let word = |i: usize| u16::try_from(arg(i)?).map_err(|_| input.to_string());
+
+        match cmd {
+            "ID" => Ok(Self::Id),
+            "CH" => Ok(Self::ConfigureHardware {
+                hardware: word(0)?,
+                options: byte(1)?,
+            }),
+            "SA" => Ok(Self::SwitchStates),
+            "SL" => {
+                let mode = match arg(1)? {
+                    0 => SwitchMode::Disabled,
+                    1 => SwitchMode::Normal,
+                    2 => SwitchMode::Inverted,
+                    _ => return Err(input.to_string()),
+                };
+                Ok(Self::SwitchConfig(SwitchConfig {
+                    switch: word(0)?,
+                    mode,
+                    debounce_close: byte(2)?,
+                    debounce_open: byte(3)?,
+                }))
+            }
+            "DL" => {
+                let mode = match arg(3)? {
+                    0x10 => DriverMode::Pulse {
+                        pulse_ms: byte(4)?,
+                        pulse_power: byte(5)?,
+                        second_ms: byte(6)?,
+                        second_power: byte(7)?,
+                        recycle_ms: byte(8)?,
+                    },
+                    0x18 => DriverMode::PulseHold {
+                        pulse_ms: byte(4)?,
+                        pulse_power: byte(5)?,
+                        hold_power: byte(6)?,
+                        recycle_ms: byte(7)?,
+                    },
+                    0x70 => DriverMode::TimedHold {
+                        pulse_ms: byte(4)?,
+                        pulse_power: byte(5)?,
+                        hold_power: byte(6)?,
+                        hold_ms: word(7)?,
+                        recycle_ms: byte(8)?,
+                    },
+                    0x75 => DriverMode::PulseHoldCancel {
+                        pulse_ms: byte(4)?,
+                        pulse_power: byte(5)?,
+                        hold_power: byte(6)?,
+                        cancel_switch: word(7)?,
+                        recycle_ms: byte(8)?,
+                    },
+                    _ => return Err(input.to_string()),
+                };
+                Ok(Self::DriverConfig(DriverConfig {
+                    driver: word(0)?,
+                    trigger: DriverTrigger::from_bits(byte(1)?),
+                    switch: word(2)?,
+                    mode,
+                }))
+            }
+            "TL" => Ok(Self::DriverTrigger {
+                driver: word(0)?,
+                control: DriverControl::from_code(byte(1)?).ok_or_else(|| input.to_string())?,
+            }),
+            "WD" => match args.first() {
+                Some(ms) => Ok(Self::Watchdog(Some(Duration::from_millis(*ms as u64)))),
+                None => Ok(Self::Watchdog(None)),
+            },
+            "NN" => Ok(Self::NodeInfo { node: byte(0)? }),
+            _ => Err(input.to_string()),
+        }
+    }
+}
+
+/// Parses comma separated hex arguments. Returns `None` if any argument is not hex
+fn parse_hex_args(all_args: &str) -> Option<Vec<u32>> {
+    if all_args.is_empty() {
+        return Some(vec![]);
+    }
+    all_args
+        .split(',')
+        .map(|arg| u32::from_str_radix(arg.trim(), 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trip(command: IoCommand) {
+        let serialized = command.to_string();
+        assert_eq!(serialized.parse::<IoCommand>(), Ok(command), "{serialized}");
+    }
+
+    #[test]
+    fn it_serializes_hardware_config() {
+        let command = IoCommand::ConfigureHardware {
+            hardware: 0x2000,
+            options: 0,
+        };
+        assert_eq!(command.to_string(), "CH:2000,0");
+    }
+
+    #[test]
+    fn it_serializes_driver_config() {
+        let command = IoCommand::DriverConfig(DriverConfig {
+            driver: 0x0a,
+            trigger: DriverTrigger {
+                enabled: true,
+                manual: true,
+                ..Default::default()
+            },
+            switch: 0,
+            mode: DriverMode::Pulse {
+                pulse_ms: 20,
+                pulse_power: 0xff,
+                second_ms: 0,
+                second_power: 0,
+                recycle_ms: 40,
+            },
+        });
+        assert_eq!(command.to_string(), "DL:0A,81,00,10,14,FF,00,00,28");
+    }
+
+    #[test]
+    fn it_round_trips_commands() {
+        assert_round_trip(IoCommand::Id);
+        assert_round_trip(IoCommand::ConfigureHardware {
+            hardware: 0x2000,
+            options: 0,
+        });
+        assert_round_trip(IoCommand::SwitchStates);
+        assert_round_trip(IoCommand::SwitchConfig(SwitchConfig {
+            switch: 0x2c,
+            mode: SwitchMode::Inverted,
+            debounce_close: 20,
+            debounce_open: 5,
+        }));
+        assert_round_trip(IoCommand::DriverTrigger {
+            driver: 3,
+            control: DriverControl::Tap,
+        });
+        assert_round_trip(IoCommand::Watchdog(Some(Duration::from_millis(1250))));
+        assert_round_trip(IoCommand::Watchdog(None));
+        assert_round_trip(IoCommand::NodeInfo { node: 2 });
+    }
+
+    #[test]
+    fn it_round_trips_driver_modes() {
+        let modes = [
+            DriverMode::Pulse {
+                pulse_ms: 10,
+                pulse_power: 0xff,
+                second_ms: 5,
+                second_power: 0x80,
+                recycle_ms: 30,
+            },
+            DriverMode::PulseHold {
+                pulse_ms: 10,
+                pulse_power: 0xff,
+                hold_power: 0x40,
+                recycle_ms: 0,
+            },
+            DriverMode::TimedHold {
+                pulse_ms: 10,
+                pulse_power: 0xff,
+                hold_power: 0x40,
+                hold_ms: 2000,
+                recycle_ms: 0,
+            },
+            DriverMode::PulseHoldCancel {
+                pulse_ms: 30,
+                pulse_power: 0xff,
+                hold_power: 0x30,
+                cancel_switch: 0x21,
+                recycle_ms: 0,
+            },
+        ];
+        for mode in modes {
+            assert_round_trip(IoCommand::DriverConfig(DriverConfig {
+                driver: 0x12,
+                trigger: DriverTrigger {
+                    enabled: true,
+                    invert_switch: true,
+                    manual: false,
+                },
+                switch: 0x05,
+                mode,
+            }));
+        }
+    }
+
+    #[test]
+    fn it_rejects_unknown_commands() {
+        assert!("ZZ:01".parse::<IoCommand>().is_err());
+        assert!("SL:GG,1,0,0".parse::<IoCommand>().is_err());
+    }
+
+    #[test]
+    fn it_rejects_values_too_large_for_their_field() {
+        assert!("SL:1FFFF,1,0,0".parse::<IoCommand>().is_err());
+        assert!("SL:2C,1,100,0".parse::<IoCommand>().is_err());
+        assert!("TL:10000,1".parse::<IoCommand>().is_err());
+        assert!("NN:100".parse::<IoCommand>().is_err());
+        // The largest values which fit still round trip
+        assert_round_trip(IoCommand::SwitchConfig(SwitchConfig {
+            switch: 0xFFFF,
+            mode: SwitchMode::Normal,
+            debounce_close: 0xFF,
+            debounce_open: 0xFF,
+        }));
+    }
+}
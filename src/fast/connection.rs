@@ -0,0 +1,198 @@
+use bevy::prelude::*;
+use std::{
+    fmt,
+    io::{Read, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{
+    command::IoCommand,
+    framing::LineBuffer,
+    parser::{parse, FastIoEvent},
+};
+
+/// State of the connection to the FAST hardware
+#[derive(States, Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub enum FastConnection {
+    #[default]
+    Connecting,
+    Connected,
+    /// The connection was lost and the ports are being re-opened
+    Reconnecting,
+    Failed(FastError),
+}
+
+/// Fired when the connection to the hardware is lost, e.g. to pause ball timers
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FastConnectionLost;
+
+/// Fired when a lost connection has been re-opened and the hardware configuration replayed
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FastConnectionRestored;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FastError {
+    /// The serial port could not be opened
+    PortOpen { path: String, reason: String },
+    /// Auto-detect did not find a port for the bus, e.g. `IO/NET` or `EXP`
+    NotFound { bus: String },
+    /// The board did not answer a command in time
+    Timeout { command: String },
+    /// The board answered a command with a failure or an unexpected response
+    Rejected { command: String, response: String },
+    /// A different controller answered `ID:` than the one configured
+    WrongController { expected: String, found: String },
+    /// The configured IO boards can't be used with the controller
+    InvalidIoBoards { reason: String },
+    /// Startup ended without a result
+    Aborted,
+}
+
+impl fmt::Display for FastError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::PortOpen { path, reason } => write!(f, "Unable to open {path}: {reason}"),
+            Self::NotFound { bus } => write!(f, "No {bus} port was found"),
+            Self::Timeout { command } => write!(f, "No response to {command}"),
+            Self::Rejected { command, response } => {
+                write!(f, "{command} was rejected with {response}")
+            }
+            Self::WrongController { expected, found } => {
+                write!(f, "Expected a {expected} but found {found}")
+            }
+            Self::InvalidIoBoards { reason } => write!(f, "{reason}"),
+            Self::Aborted => write!(f, "Startup ended without a result"),
+        }
+    }
+}
+
+impl std::error::Error for FastError {}
+
+/// Time to wait for the board to answer a command
+const RESPONSE_WAIT: Duration = Duration::from_millis(50);
+
+/// Send a command and wait briefly for its response
+pub fn request<P: Read + Write + ?Sized>(
+    port: &mut P,
+    command: &IoCommand,
+) -> Result<FastIoEvent, FastError> {
+    let _ = port.write_all(format!("{command}\r").as_bytes());
+    thread::sleep(RESPONSE_WAIT);
+    let mut resp = String::new();
+    let _ = port.read_to_string(&mut resp);
+    trace!("{command} response: {resp}");
+
+    // Responses end with a `\r`, but be lenient with a board which leaves it off
+    let mut lines = LineBuffer::default();
+    let mut messages = lines.push(&resp);
+    if !resp.ends_with('\r') {
+        messages.extend(lines.push("\r"));
+    }
+
+    // Skip over unrelated messages, such as switch reports, that arrived in the meantime
+    let mut last = None;
+    for message in messages {
+        match parse(message.clone()) {
+            Ok(FastIoEvent::SwitchOpened { .. } | FastIoEvent::SwitchClosed { .. }) => {}
+            Ok(event) => return Ok(event),
+            Err(_) => last = Some(message),
+        }
+    }
+    match last {
+        Some(response) => Err(FastError::Rejected {
+            command: command.to_string(),
+            response,
+        }),
+        None => Err(FastError::Timeout {
+            command: command.to_string(),
+        }),
+    }
+}
+
+/// Ask the board to identify itself until it answers or the deadline passes, e.g. while
+/// it is still booting
+pub fn identify<P: Read + Write + ?Sized>(
+    port: &mut P,
+    deadline: Instant,
+) -> Result<FastIoEvent, FastError> {
+    loop {
+        let result = request(port, &IoCommand::Id);
+        match result {
+            Ok(event @ FastIoEvent::Identity { .. }) => {
+                debug!("{event}");
+                return Ok(event);
+            }
+            _ if Instant::now() < deadline => continue,
+            Ok(event) => {
+                return Err(FastError::Rejected {
+                    command: IoCommand::Id.to_string(),
+                    response: event.to_string(),
+                });
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Send a command which the board acknowledges with `XX:P`
+pub fn configure<P: Read + Write + ?Sized>(
+    port: &mut P,
+    command: &IoCommand,
+) -> Result<(), FastError> {
+    match request(port, command)? {
+        FastIoEvent::Passed { .. } => Ok(()),
+        event => Err(FastError::Rejected {
+            command: command.to_string(),
+            response: event.to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fast::mock_port::MockPort;
+
+    #[test]
+    fn it_accepts_acknowledged_commands() {
+        let mut port = MockPort::default();
+        port.respond("CH:P\r");
+        let command = IoCommand::ConfigureHardware {
+            hardware: 0x2000,
+            options: 0,
+        };
+        assert_eq!(configure(&mut port, &command), Ok(()));
+        assert_eq!(port.written(), vec!["CH:2000,0"]);
+    }
+
+    #[test]
+    fn it_reports_rejected_commands() {
+        let mut port = MockPort::default();
+        port.respond("CH:F\r");
+        let command = IoCommand::ConfigureHardware {
+            hardware: 0x2000,
+            options: 0,
+        };
+        assert_eq!(
+            configure(&mut port, &command),
+            Err(FastError::Rejected {
+                command: "CH:2000,0".to_string(),
+                response: "CH:F".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn it_times_out_when_the_board_never_answers() {
+        let mut port = MockPort::default();
+        let result = identify(&mut port, Instant::now() + Duration::from_millis(120));
+        assert_eq!(
+            result,
+            Err(FastError::Timeout {
+                command: "ID:".to_string()
+            })
+        );
+        assert!(port.written().len() >= 2);
+    }
+}
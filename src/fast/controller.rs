@@ -0,0 +1,269 @@
+use std::{
+    fmt,
+    io::{Read, Write},
+    time::Instant,
+};
+
+use super::{
+    command::IoCommand,
+    connection::{FastError, configure, identify},
+    io_board::IoBoard,
+    parser::FastIoEvent,
+};
+
+/// FastController - The FAST controller at the other end of the IO/NET port
+///
+/// Controllers share the serial layer, but each identifies itself differently, takes its
+/// own hardware configuration at startup, and numbers its switches and drivers its own
+/// way.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FastController {
+    /// FP-CPU-2000, with IO boards on the IO/NET loop and LEDs on the EXP bus
+    #[default]
+    Neutron,
+    /// FP-CPU-002, with IO boards on the IO/NET loop but no EXP bus. It predates `CH:`.
+    /// Like the Neutron it has no switches or drivers of its own, and numbers those of its
+    /// IO boards one after another in loop order, so `IoNetLoop` numbers them for both.
+    Nano,
+    /// Replaces the CPU board of an older machine, driving its switch matrix and solenoids
+    Retro(RetroPlatform),
+}
+
+/// The machine platform a Retro controller replaces the CPU board of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RetroPlatform {
+    /// Williams System 11
+    Sys11,
+    /// Williams WPC-89, including Fliptronics
+    Wpc89,
+    /// Williams WPC-95
+    Wpc95,
+}
+
+impl FastController {
+    /// The product the controller identifies as with `ID:`, less its revision
+    pub fn product(&self) -> &'static str {
+        match self {
+            Self::Neutron => "FP-CPU-2000",
+            Self::Nano => "FP-CPU-002",
+            Self::Retro(RetroPlatform::Sys11) => "FP-SYS11",
+            Self::Retro(RetroPlatform::Wpc89) => "FP-WPC89",
+            Self::Retro(RetroPlatform::Wpc95) => "FP-WPC95",
+        }
+    }
+
+    /// The `CH:` telling the controller which hardware it is running, for those which take
+    /// one
+    pub fn hardware(&self) -> Option<IoCommand> {
+        let hardware = match self {
+            Self::Neutron => 0x2000,
+            Self::Nano => return None,
+            Self::Retro(RetroPlatform::Sys11) => 0x1100,
+            Self::Retro(RetroPlatform::Wpc89) => 0x8900,
+            Self::Retro(RetroPlatform::Wpc95) => 0x9500,
+        };
+        Some(IoCommand::ConfigureHardware {
+            hardware,
+            options: 0,
+        })
+    }
+
+    /// Whether expansion boards are reached on a separate EXP port
+    pub fn has_exp_bus(&self) -> bool {
+        matches!(self, Self::Neutron)
+    }
+
+    /// Whether the IO boards are on a loop which answers `NN:`
+    pub fn has_io_loop(&self) -> bool {
+        !matches!(self, Self::Retro(_))
+    }
+
+    /// Wait for the controller to boot, make sure it's the expected one, then configure it
+    pub fn start<P: Read + Write + ?Sized>(
+        &self,
+        port: &mut P,
+        deadline: Instant,
+    ) -> Result<(), FastError> {
+        match identify(port, deadline)? {
+            FastIoEvent::Identity { product, .. } if product.starts_with(self.product()) => {}
+            event => {
+                return Err(FastError::WrongController {
+                    expected: self.product().to_string(),
+                    found: event.to_string(),
+                });
+            }
+        }
+        match self.hardware() {
+            Some(command) => configure(port, &command),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks the IO boards suit the controller. A Retro's own switches and drivers come
+    /// first, as an `IoBoard::Retro`, which no other controller has.
+    pub fn check_io_boards(&self, io_boards: &[IoBoard]) -> Result<(), FastError> {
+        let retro_boards = io_boards
+            .iter()
+            .enumerate()
+            .filter_map(|(position, board)| match board {
+                IoBoard::Retro { platform, .. } => Some((position, *platform)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let reason = match (self, retro_boards.as_slice()) {
+            (Self::Retro(platform), [(0, found)]) if found == platform => return Ok(()),
+            (Self::Retro(platform), _) => format!(
+                "The first IO board of a {self} must be the only IoBoard::Retro, for {platform:?}"
+            ),
+            (_, []) => return Ok(()),
+            (_, _) => {
+                format!("IoBoard::Retro can only be used with a Retro controller, not a {self}")
+            }
+        };
+        Err(FastError::InvalidIoBoards { reason })
+    }
+}
+
+impl fmt::Display for FastController {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Neutron => write!(f, "Neutron"),
+            Self::Nano => write!(f, "Nano"),
+            Self::Retro(platform) => write!(f, "Retro {platform:?}"),
+        }
+    }
+}
+
+impl RetroPlatform {
+    /// Gets the total number of switches, the matrix first then any dedicated switches
+    pub fn switch_port_count(&self) -> u8 {
+        match self {
+            Self::Sys11 => 64,
+            Self::Wpc89 | Self::Wpc95 => 72,
+        }
+    }
+
+    /// Gets the total number of drivers, solenoids first then any flipper drivers
+    pub fn coil_port_count(&self) -> u8 {
+        match self {
+            Self::Sys11 => 32,
+            Self::Wpc89 | Self::Wpc95 => 36,
+        }
+    }
+
+    /// Gets the port of a matrix switch as numbered in the machine's manual, e.g. `35` for
+    /// column 3, row 5
+    pub fn matrix_switch(&self, number: u8) -> Option<u8> {
+        let (column, row) = (number / 10, number % 10);
+        match (1..=8).contains(&column) && (1..=8).contains(&row) {
+            true => Some((column - 1) * 8 + row - 1),
+            false => None,
+        }
+    }
+
+    /// Gets the port of a dedicated switch, e.g. `1` for the WPC cabinet's D1
+    pub fn dedicated_switch(&self, number: u8) -> Option<u8> {
+        match (self, number) {
+            (Self::Sys11, _) => None,
+            (_, 1..=8) => Some(64 + number - 1),
+            _ => None,
+        }
+    }
+
+    /// Gets the port of a solenoid as numbered in the machine's manual, starting at `1`
+    pub fn solenoid(&self, number: u8) -> Option<u8> {
+        match number >= 1 && number <= self.coil_port_count() {
+            true => Some(number - 1),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fast::{io_board::IoNetLoop, mock_port::MockPort};
+
+    #[test]
+    fn it_identifies_the_controller() {
+        // The Nano takes no `CH:`, so it's ready once identified
+        let mut port = MockPort::default();
+        port.respond("ID:NET FP-CPU-002-1 01.05\r");
+        assert_eq!(
+            FastController::Nano.start(&mut port, Instant::now()),
+            Ok(())
+        );
+        assert_eq!(port.written(), vec!["ID:"]);
+
+        let mut port = MockPort::default();
+        port.respond("ID:NET FP-CPU-002-1 01.05\r");
+        assert_eq!(
+            FastController::Neutron.start(&mut port, Instant::now()),
+            Err(FastError::WrongController {
+                expected: "FP-CPU-2000".to_string(),
+                found: "ID:NET FP-CPU-002-1 01.05".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn it_numbers_nano_io_boards_like_the_neutron() {
+        let io_loop = IoNetLoop(vec![
+            IoBoard::Fast0804 {
+                switches: vec![],
+                coils: vec![],
+            },
+            IoBoard::Fast3208 {
+                switches: vec![],
+                coils: vec![],
+            },
+            IoBoard::Fast1616 {
+                switches: vec![],
+                coils: vec![],
+            },
+        ]);
+        // The Nano's numbers for the first port of each board, and the last port of the
+        // last
+        let switches = [(0, 0, 0x00), (1, 0, 0x08), (2, 0, 0x28), (2, 15, 0x37)];
+        for (board, port, number) in switches {
+            assert_eq!(io_loop.switch_number(board, port), Some(number));
+        }
+        let drivers = [(0, 0, 0x00), (1, 0, 0x04), (2, 0, 0x0c), (2, 15, 0x1b)];
+        for (board, port, number) in drivers {
+            assert_eq!(io_loop.driver_number(board, port), Some(number));
+        }
+        assert!(FastController::Nano.has_io_loop());
+    }
+
+    #[test]
+    fn it_checks_for_the_retro_board() {
+        let retro = |platform| IoBoard::Retro {
+            platform,
+            switches: vec![],
+            coils: vec![],
+        };
+        let wpc = FastController::Retro(RetroPlatform::Wpc95);
+        assert_eq!(wpc.check_io_boards(&[retro(RetroPlatform::Wpc95)]), Ok(()));
+        assert!(wpc.check_io_boards(&[]).is_err());
+        assert!(wpc.check_io_boards(&[retro(RetroPlatform::Sys11)]).is_err());
+        assert!(
+            FastController::Neutron
+                .check_io_boards(&[retro(RetroPlatform::Wpc95)])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn it_numbers_retro_switches_and_solenoids() {
+        let wpc = RetroPlatform::Wpc95;
+        assert_eq!(wpc.matrix_switch(11), Some(0));
+        assert_eq!(wpc.matrix_switch(35), Some(20));
+        assert_eq!(wpc.matrix_switch(88), Some(63));
+        assert_eq!(wpc.matrix_switch(19), None);
+        assert_eq!(wpc.dedicated_switch(1), Some(64));
+        assert_eq!(RetroPlatform::Sys11.dedicated_switch(1), None);
+        assert_eq!(wpc.solenoid(1), Some(0));
+        assert_eq!(wpc.solenoid(0), None);
+        assert_eq!(wpc.solenoid(37), None);
+    }
+}
@@ -0,0 +1,381 @@
+use bevy::prelude::*;
+use std::{hash::Hash, time::Duration};
+
+use crate::pinball::Identity;
+
+use super::{
+    command::{DriverConfig, DriverControl, DriverMode, DriverTrigger, IoCommand},
+    connection::FastConnection,
+    io_board::IoNetLoop,
+    resources::IoNetPort,
+    rules::{HardwareRuleSet, HardwareRulesEnabled},
+    serial::io_write,
+};
+
+/// FastDrivers - Drivers (coils, flashers, motors, etc.) on the IO/NET loop
+///
+/// Each driver is spawned as an entity with a `Driver` and an `Identity<T>`, and is
+/// fired by sending a `DriverCommand<T>` event. The `Neutron` plugin must be added
+/// first as it provides the IO board layout. While the `HardwareRules` are enabled, drivers
+/// fired by a rule belong to that rule, and commands for them are refused.
+pub struct FastDrivers<T: Copy + Eq + Hash + Send + Sync + 'static> {
+    pub drivers: Vec<DriverDefinition<T>>,
+}
+
+/// Configuration for a single driver
+#[derive(Debug, Clone)]
+pub struct DriverDefinition<T: Copy + Eq + Hash + Send + Sync + 'static> {
+    pub id: T,
+    /// Position of the IO board in the loop. The board nearest the Neutron = `0`
+    pub board: usize,
+    /// Driver port on the IO board
+    pub port: u8,
+    pub name: &'static str,
+}
+
+/// Driver -- A driver on the IO/NET loop, addressed by its FAST driver number
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Driver {
+    pub number: u16,
+}
+
+/// How hard and how long a driver is fired
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DriverSettings {
+    /// Time in ms the driver is fired at full power
+    pub pulse_ms: u8,
+    /// PWM duty while held, from `0` (off) to `255` (full power)
+    pub hold_power: u8,
+    /// Time in ms before the driver can be fired again
+    pub recycle_ms: u8,
+}
+
+impl Default for DriverSettings {
+    fn default() -> Self {
+        Self {
+            pulse_ms: 10,
+            hold_power: 0,
+            recycle_ms: 10,
+        }
+    }
+}
+
+/// Fire, hold or release a driver
+#[derive(Event, Debug, Clone)]
+pub struct DriverCommand<T: Copy + Eq + Hash + Send + Sync + 'static> {
+    pub id: T,
+    pub action: DriverAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriverAction {
+    /// Fire the driver once, e.g. kick a ball out of a saucer
+    Pulse(DriverSettings),
+    /// Fire the driver then hold it at `hold_power` until disabled
+    PulseHold(DriverSettings),
+    /// Fire the driver then hold it at `hold_power` for the given time
+    Enable(DriverSettings, Duration),
+    /// Turn the driver off
+    Disable,
+}
+
+impl<T: Copy + Eq + Hash + Send + Sync + 'static> Plugin for FastDrivers<T> {
+    fn build(&self, app: &mut App) {
+        let io_loop = app
+            .world()
+            .get_resource::<IoNetLoop>()
+            .cloned()
+            .unwrap_or_default();
+
+        for definition in self.drivers.iter() {
+            let Some(number) = io_loop.driver_number(definition.board, definition.port) else {
+                error!(
+                    "No driver port {} on IO board {}",
+                    definition.port, definition.board
+                );
+                continue;
+            };
+
+            let mut entity = app
+                .world_mut()
+                .spawn((Driver { number }, Identity { id: definition.id }));
+
+            // Name
+            if !definition.name.is_empty() {
+                entity.insert(Name::new(definition.name));
+            }
+        }
+
+        app.add_event::<DriverCommand<T>>();
+        app.add_systems(
+            Update,
+            driver_command_listener::<T>.run_if(in_state(FastConnection::Connected)),
+        );
+    }
+}
+
+fn driver_command_listener<T: Copy + Eq + Hash + Send + Sync + 'static>(
+    mut ev: EventReader<DriverCommand<T>>,
+    query: Query<(&Driver, &Identity<T>, Option<&Name>)>,
+    rule_set: Option<Res<HardwareRuleSet>>,
+    rules_enabled: Option<Res<HardwareRulesEnabled>>,
+    port: ResMut<IoNetPort>,
+) {
+    for command in ev.read() {
+        for (driver, identity, name) in &query {
+            if identity.id != command.id {
+                continue;
+            }
+            // Sending `DL:` would overwrite the rule's switch and mode. While the rules are
+            // disabled the driver is free, as enabling them sends every rule again.
+            let rules_enabled = rules_enabled.as_ref().is_some_and(|enabled| enabled.0);
+            if rules_enabled
+                && rule_set.as_ref().is_some_and(|rule_set| {
+                    rule_set
+                        .0
+                        .iter()
+                        .any(|config| config.driver == driver.number)
+                })
+            {
+                error!(
+                    "Driver {} is fired by a hardware rule, ignoring {:?}",
+                    name.map_or(driver.number.to_string(), |name| name.to_string()),
+                    command.action
+                );
+                continue;
+            }
+            for data in driver_commands(driver.number, command.action) {
+                io_write(data.to_string(), &port);
+            }
+        }
+    }
+}
+
+/// Translate a driver action into the FAST commands which perform it
+pub(crate) fn driver_commands(driver: u16, action: DriverAction) -> Vec<IoCommand> {
+    let manual = DriverTrigger {
+        enabled: true,
+        manual: true,
+        ..Default::default()
+    };
+    let (mode, control) = match action {
+        DriverAction::Pulse(settings) => (
+            DriverMode::Pulse {
+                pulse_ms: settings.pulse_ms,
+                pulse_power: 0xff,
+                second_ms: 0,
+                second_power: 0,
+                recycle_ms: settings.recycle_ms,
+            },
+            DriverControl::Tap,
+        ),
+        DriverAction::PulseHold(settings) => (
+            DriverMode::PulseHold {
+                pulse_ms: settings.pulse_ms,
+                pulse_power: 0xff,
+                hold_power: settings.hold_power,
+                recycle_ms: settings.recycle_ms,
+            },
+            DriverControl::On,
+        ),
+        DriverAction::Enable(settings, duration) => (
+            DriverMode::TimedHold {
+                pulse_ms: settings.pulse_ms,
+                pulse_power: 0xff,
+                hold_power: settings.hold_power,
+                hold_ms: duration.as_millis().min(u16::MAX as u128) as u16,
+                recycle_ms: settings.recycle_ms,
+            },
+            DriverControl::Tap,
+        ),
+        DriverAction::Disable => {
+            return vec![IoCommand::DriverTrigger {
+                driver,
+                control: DriverControl::Off,
+            }];
+        }
+    };
+
+    vec![
+        IoCommand::DriverConfig(DriverConfig {
+            driver,
+            trigger: manual,
+            switch: 0,
+            mode,
+        }),
+        IoCommand::DriverTrigger { driver, control },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fast::{
+            IoBoard,
+            io_board::IoAddress,
+            mock_port::MockPort,
+            rules::{HardwareRule, HardwareRules, RuleKind},
+        },
+        pinball::{MachineState, Tilt},
+    };
+    use bevy::state::app::StatesPlugin;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn it_pulses_drivers() {
+        let settings = DriverSettings {
+            pulse_ms: 25,
+            hold_power: 0,
+            recycle_ms: 50,
+        };
+        let frames = driver_commands(0x0c, DriverAction::Pulse(settings))
+            .iter()
+            .map(|command| command.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(frames, vec!["DL:0C,81,00,10,19,FF,00,00,32", "TL:0C,3"]);
+    }
+
+    #[test]
+    fn it_holds_drivers_for_a_time() {
+        let settings = DriverSettings {
+            pulse_ms: 20,
+            hold_power: 0x40,
+            recycle_ms: 0,
+        };
+        let frames = driver_commands(
+            2,
+            DriverAction::Enable(settings, Duration::from_millis(1500)),
+        )
+        .iter()
+        .map(|command| command.to_string())
+        .collect::<Vec<_>>();
+        assert_eq!(frames, vec!["DL:02,81,00,70,14,FF,40,05DC,00", "TL:02,3"]);
+    }
+
+    #[test]
+    fn it_disables_drivers() {
+        let commands = driver_commands(2, DriverAction::Disable);
+        assert_eq!(
+            commands,
+            vec![IoCommand::DriverTrigger {
+                driver: 2,
+                control: DriverControl::Off,
+            }]
+        );
+    }
+
+    #[test]
+    fn it_leaves_ruled_drivers_to_their_rule() {
+        let port = MockPort::default();
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin));
+        app.insert_state(FastConnection::Connected);
+        app.init_state::<MachineState>();
+        app.add_event::<Tilt>();
+        app.insert_resource(IoNetPort(Arc::new(Mutex::new(Box::new(port.clone())))));
+        app.insert_resource(IoNetLoop(vec![IoBoard::Fast3208 {
+            switches: vec![],
+            coils: vec![],
+        }]));
+        app.add_plugins(HardwareRules {
+            rules: vec![HardwareRule {
+                name: "sling",
+                switch: IoAddress { board: 0, port: 0 },
+                driver: IoAddress { board: 0, port: 0 },
+                kind: RuleKind::PulseOnClose {
+                    pulse_ms: 10,
+                    recycle_ms: 10,
+                },
+            }],
+        });
+        app.add_plugins(FastDrivers {
+            drivers: vec![
+                DriverDefinition {
+                    id: 0,
+                    board: 0,
+                    port: 0,
+                    name: "sling",
+                },
+                DriverDefinition {
+                    id: 1,
+                    board: 0,
+                    port: 1,
+                    name: "kicker",
+                },
+            ],
+        });
+        app.insert_resource(HardwareRulesEnabled(true));
+        app.update();
+        let rule = port.written();
+        assert!(rule.iter().any(|frame| frame.starts_with("DL:00,01")));
+
+        for id in [0, 1] {
+            app.world_mut().send_event(DriverCommand {
+                id,
+                action: DriverAction::Pulse(DriverSettings::default()),
+            });
+        }
+        app.update();
+
+        // Only the driver without a rule is reconfigured and fired
+        let written = port.written()[rule.len()..].to_vec();
+        assert_eq!(written, vec!["DL:01,81,00,10,0A,FF,00,00,0A", "TL:01,3"]);
+        assert!(rule.iter().all(|frame| !frame.starts_with("DL:01")));
+    }
+
+    #[test]
+    fn it_fires_ruled_drivers_while_the_rules_are_disabled() {
+        let port = MockPort::default();
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin));
+        app.insert_state(FastConnection::Connected);
+        app.init_state::<MachineState>();
+        app.add_event::<Tilt>();
+        app.insert_resource(IoNetPort(Arc::new(Mutex::new(Box::new(port.clone())))));
+        app.insert_resource(IoNetLoop(vec![IoBoard::Fast3208 {
+            switches: vec![],
+            coils: vec![],
+        }]));
+        app.add_plugins(HardwareRules {
+            rules: vec![HardwareRule {
+                name: "sling",
+                switch: IoAddress { board: 0, port: 0 },
+                driver: IoAddress { board: 0, port: 0 },
+                kind: RuleKind::PulseOnClose {
+                    pulse_ms: 10,
+                    recycle_ms: 10,
+                },
+            }],
+        });
+        app.add_plugins(FastDrivers {
+            drivers: vec![DriverDefinition {
+                id: 0,
+                board: 0,
+                port: 0,
+                name: "sling",
+            }],
+        });
+        app.update();
+        let rule = port.written();
+        assert!(rule.iter().any(|frame| frame.starts_with("DL:00,00")));
+
+        // e.g. a coil test outside of a game
+        app.world_mut().send_event(DriverCommand {
+            id: 0,
+            action: DriverAction::Pulse(DriverSettings::default()),
+        });
+        app.update();
+        let written = port.written()[rule.len()..].to_vec();
+        assert_eq!(written, vec!["DL:00,81,00,10,0A,FF,00,00,0A", "TL:00,3"]);
+
+        // Enabling the rules gives the driver back to its rule
+        app.insert_resource(HardwareRulesEnabled(true));
+        app.update();
+        assert!(
+            port.written()
+                .last()
+                .is_some_and(|frame| frame.starts_with("DL:00,01"))
+        );
+    }
+}
@@ -1,14 +1,34 @@
 use bevy::{color::palettes::css::BLACK, prelude::*, time::common_conditions::on_timer};
-use std::{fmt::Debug, time::Duration};
+use std::{collections::BTreeSet, fmt::Debug, time::Duration};
 
-use crate::pinball::{PinballConfig, RgbLed};
+use crate::pinball::{PinballConfig, RgbLed, TransitionMode};
 
-use super::{resources::ExpPort, serial::exp_write, ExpansionBoard};
+use super::{
+    calibration::{CalibrationTable, LedCalibration, LedType, PortCalibration, WhiteBalance},
+    connection::FastConnection,
+    led_frame::LedFrames,
+    parser::ExpBusEvent,
+    resources::ExpPort,
+    serial::{exp_identify, exp_write, ExpIdentityQueries},
+    ExpansionBoard,
+};
 
 pub struct ExpansionLeds {
     pub leds: Vec<LedDefinition>,
     /// How frequently to send out updates to LEDs; given in Hz/FPS
     pub update_hz: f32,
+    /// Most bytes to send on the EXP bus per update. Changes which don't fit are coalesced
+    /// and sent on a later update. The default leaves headroom on the 921600 baud bus.
+    pub max_bytes_per_update: usize,
+    /// Hand linear animation stages to the expansion boards as a single hardware fade
+    pub hardware_fades: bool,
+    /// Time to re-send every LED over, a few at a time, in case a frame was dropped.
+    /// `None` only sends LEDs when they change.
+    pub resync_period: Option<Duration>,
+    /// Gamma applied to every LED; 1.0 sends colors as they are
+    pub gamma: f32,
+    /// LED type, color order and white balance of the LED strips on each port
+    pub port_calibration: Vec<PortCalibration>,
 }
 
 impl Default for ExpansionLeds {
@@ -16,21 +36,63 @@ impl Default for ExpansionLeds {
         Self {
             leds: Default::default(),
             update_hz: 30.,
+            max_bytes_per_update: 2048,
+            hardware_fades: false,
+            resync_period: Some(Duration::from_secs(10)),
+            gamma: 1.,
+            port_calibration: vec![],
         }
     }
 }
 
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpansionLedConfig {
+    pub update_interval: Duration,
+    pub max_bytes_per_update: usize,
+    pub resync_period: Option<Duration>,
+}
+
 impl Plugin for ExpansionLeds {
     fn build(&self, app: &mut App) {
+        // LEDs with the same calibration share a table
+        let mut tables: Vec<(LedCalibration, CalibrationTable)> = vec![];
         for definition in self.leds.iter() {
+            let layout = self.layout(definition);
+            if !layout.fits() {
+                error!(
+                    "LED {} at index {} is past the end of port {} on expansion board {}",
+                    definition.name, definition.index, definition.port, definition.board
+                );
+                continue;
+            }
+
+            let calibration = self.calibration(definition);
+            let table = match tables.iter().find(|(c, _)| *c == calibration) {
+                Some((_, table)) => table.clone(),
+                None => {
+                    let table = CalibrationTable::new(&calibration);
+                    tables.push((calibration, table.clone()));
+                    table
+                }
+            };
+
             // spawn entities for LEDs
             let mut entity = app.world_mut().spawn((
-                RgbLed { color: BLACK },
+                RgbLed {
+                    color: BLACK,
+                    transition_mode: match self.hardware_fades {
+                        true => TransitionMode::HardwareFade,
+                        false => TransitionMode::Rendered,
+                    },
+                    ..Default::default()
+                },
                 FastExpansionDevice {
                     expansion_address: definition.board.as_str(),
                     port: definition.port,
                     index: definition.index,
                 },
+                layout,
+                table,
             ));
 
             // Name
@@ -40,39 +102,192 @@ impl Plugin for ExpansionLeds {
         }
 
         let update_led_duration = Duration::from_secs_f32(1. / self.update_hz);
+        app.insert_resource(ExpansionLedConfig {
+            update_interval: update_led_duration,
+            max_bytes_per_update: self.max_bytes_per_update,
+            resync_period: self.resync_period,
+        });
         app.add_systems(
             FixedLast,
-            led_change_listener.run_if(on_timer(update_led_duration)),
+            (resync_leds, led_change_listener)
+                .chain()
+                .run_if(in_state(FastConnection::Connected))
+                .run_if(on_timer(update_led_duration)),
         );
+
+        // The boards forget their LEDs when they reset, so send every color after a (re)connect
+        app.init_resource::<LedFrames>();
+        app.add_systems(OnEnter(FastConnection::Connected), resend_leds);
+
+        // Check a board answers at every address, so a misconfigured LED is reported at startup
+        app.add_event::<ExpBusEvent>();
+        app.init_resource::<ExpIdentityQueries>();
+        app.add_systems(OnEnter(FastConnection::Connected), identify_boards);
+        app.add_systems(Update, report_boards);
+    }
+}
+
+impl ExpansionLeds {
+    fn port_calibration(&self, definition: &LedDefinition) -> Option<&PortCalibration> {
+        self.port_calibration
+            .iter()
+            .find(|port| port.board == definition.board && port.port == definition.port)
+    }
+
+    fn led_type(&self, definition: &LedDefinition) -> LedType {
+        definition
+            .led_type
+            .or(self.port_calibration(definition).map(|port| port.led_type))
+            .unwrap_or_default()
+    }
+
+    /// Where the LED's bytes start on its port. LEDs before it which aren't defined are
+    /// taken to be the port's LED type.
+    fn layout(&self, definition: &LedDefinition) -> LedLayout {
+        let default_type = self.led_type(&LedDefinition {
+            led_type: None,
+            ..definition.clone()
+        });
+        let offset = (0..definition.index)
+            .map(|index| {
+                self.leds
+                    .iter()
+                    .find(|led| {
+                        led.board == definition.board
+                            && led.port == definition.port
+                            && led.index == index
+                    })
+                    .map_or(default_type, |led| self.led_type(led))
+                    .channel_count()
+            })
+            .sum();
+        LedLayout {
+            led_type: self.led_type(definition),
+            offset,
+        }
+    }
+
+    /// The LED's own white balance wins over its port's
+    fn calibration(&self, definition: &LedDefinition) -> LedCalibration {
+        let port = self.port_calibration(definition);
+        LedCalibration {
+            gamma: self.gamma,
+            channel_order: port.map(|port| port.channel_order).unwrap_or_default(),
+            white_balance: definition
+                .white_balance
+                .or(port.map(|port| port.white_balance))
+                .unwrap_or_default(),
+        }
     }
 }
 
+fn identify_boards(
+    query: Query<&FastExpansionDevice>,
+    mut queries: ResMut<ExpIdentityQueries>,
+    time: Res<Time<Real>>,
+    port: ResMut<ExpPort>,
+) {
+    let addresses = query
+        .iter()
+        .map(|led| led.expansion_address)
+        .collect::<BTreeSet<_>>();
+    for address in addresses {
+        exp_identify(address, time.elapsed(), &port, &mut queries);
+    }
+}
+
+fn report_boards(mut ev_exp: EventReader<ExpBusEvent>, query: Query<&FastExpansionDevice>) {
+    for event in ev_exp.read() {
+        match event {
+            ExpBusEvent::Identity {
+                address,
+                product,
+                firmware,
+                ..
+            } => info!("Found {product} {firmware} at expansion address {address}"),
+            ExpBusEvent::BoardMissing { address } => {
+                let count = query
+                    .iter()
+                    .filter(|led| led.expansion_address == address)
+                    .count();
+                error!(
+                    "No expansion board at address {address}, so its {count} LEDs will never light. Check the board and jumpers of their LedDefinitions."
+                );
+            }
+            ExpBusEvent::Failed { command } => debug!("EXP command {command} failed"),
+            ExpBusEvent::Passed { .. } => {}
+        }
+    }
+}
+
+fn resend_leds(
+    mut query: Query<&mut RgbLed, With<FastExpansionDevice>>,
+    mut pending: ResMut<LedFrames>,
+) {
+    pending.reset_fades();
+    for mut led in &mut query {
+        led.set_changed();
+    }
+}
+
+/// Marks the next few LEDs as changed each update, so every LED is re-sent once per
+/// `resync_period` even if its color never changes
+fn resync_leds(
+    mut query: Query<&mut RgbLed, With<FastExpansionDevice>>,
+    config: Res<ExpansionLedConfig>,
+    mut cursor: Local<usize>,
+) {
+    let Some(period) = config.resync_period else {
+        return;
+    };
+    let total = query.iter().len();
+    if total == 0 {
+        return;
+    }
+    let updates_per_period = period.as_secs_f32() / config.update_interval.as_secs_f32();
+    let per_update = ((total as f32 / updates_per_period).ceil() as usize).clamp(1, total);
+
+    let start = *cursor % total;
+    for (i, mut led) in query.iter_mut().enumerate() {
+        if (i + total - start) % total < per_update {
+            led.set_changed();
+        }
+    }
+    *cursor = (start + per_update) % total;
+}
+
+/// An LED's color, where its bytes go, and how to correct it
+type ExpansionLedQuery<'a> = (
+    &'a mut RgbLed,
+    &'a FastExpansionDevice,
+    Option<&'a LedLayout>,
+    Option<&'a CalibrationTable>,
+);
+
 fn led_change_listener(
-    query: Query<(&RgbLed, &FastExpansionDevice), Changed<RgbLed>>,
+    mut query: Query<ExpansionLedQuery, Changed<RgbLed>>,
     pinball_config: Res<PinballConfig>,
+    config: Res<ExpansionLedConfig>,
+    mut pending: ResMut<LedFrames>,
     port: ResMut<ExpPort>,
 ) {
-    for (indicator, led) in &query {
-        let color = if pinball_config.led_luminance_scale != 1.0 {
-            // scale brightness if not 1.0
-            let hsl = Hsla::from(indicator.color);
-            Srgba::from(hsl.with_lightness(hsl.lightness * pinball_config.led_luminance_scale))
-        } else {
-            indicator.color
-        };
-        let data = led_color_event(led, color);
-        exp_write(data, &port);
+    let uncalibrated = CalibrationTable::default();
+    for (mut indicator, led, layout, table) in &mut query {
+        let layout = layout.copied().unwrap_or(LedLayout::rgb(led.index));
+        let table = table.unwrap_or(&uncalibrated);
+        let color = table.apply(indicator.color, pinball_config.led_luminance_scale);
+        pending.queue(led, &layout, color, indicator.fade);
+        // A fade is for a single color, so whatever sets the next one fades it itself
+        if !indicator.fade.is_zero() {
+            indicator.bypass_change_detection().fade = Duration::ZERO;
+        }
     }
-}
 
-fn led_color_event(led: &FastExpansionDevice, color: Srgba) -> String {
-    format!(
-        "RS@{}{}:{}{}",
-        led.expansion_address,
-        led.port,
-        led.index,
-        hsl_to_hex(color),
-    )
+    // Send every frame in a single write
+    let frames = pending.take(config.max_bytes_per_update);
+    if !frames.is_empty() {
+        exp_write(frames.join("\r"), &port);
+    }
 }
 
 /// FastLED -- Hardware attached to a Fast expansion board
@@ -85,6 +300,28 @@ pub struct FastExpansionDevice {
     pub index: u8,
 }
 
+/// The chip of an expansion LED, and where its bytes start in the data sent down its port
+#[derive(Component, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct LedLayout {
+    pub led_type: LedType,
+    pub offset: usize,
+}
+
+impl LedLayout {
+    /// An LED on a port of only RGB LEDs
+    pub fn rgb(index: u8) -> Self {
+        Self {
+            led_type: LedType::Rgb,
+            offset: index as usize * 3,
+        }
+    }
+
+    /// Whether the LED's bytes are within the 256 indexes of three bytes a port addresses
+    pub fn fits(&self) -> bool {
+        self.offset + self.led_type.channel_count() <= 256 * 3
+    }
+}
+
 /// Configuration for a single LED
 /// See: https://fastpinball.com/programming/exp/#expansion-board-addresses
 #[derive(Debug, Default, Clone)]
@@ -93,30 +330,191 @@ pub struct LedDefinition {
     pub port: u8,
     pub index: u8,
     pub name: &'static str,
+    /// Overrides the LED type of the port, for ports with a mix of chips
+    pub led_type: Option<LedType>,
+    /// Overrides the white balance of the port
+    pub white_balance: Option<WhiteBalance>,
 }
 
-fn hsl_to_hex(rgb: Srgba) -> String {
-    format!(
-        "{:0>2x}{:0>2x}{:0>2x}",
-        (rgb.red * 255.) as u16,
-        (rgb.green * 255.) as u16,
-        (rgb.blue * 255.) as u16
-    )
+pub(super) fn rgb_to_hex([red, green, blue]: [u8; 3]) -> String {
+    format!("{red:0>2x}{green:0>2x}{blue:0>2x}")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fast::mock_port::MockPort;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn it_resyncs_a_few_leds_each_update() {
+        let port = MockPort::default();
+        let mut app = App::new();
+        app.insert_resource(ExpPort(Arc::new(Mutex::new(Box::new(port.clone())))));
+        app.init_resource::<LedFrames>();
+        app.insert_resource(PinballConfig {
+            led_luminance_scale: 1.0,
+        });
+        app.insert_resource(ExpansionLedConfig {
+            update_interval: Duration::from_millis(100),
+            max_bytes_per_update: 2048,
+            resync_period: Some(Duration::from_millis(200)),
+        });
+        for index in 0..4 {
+            app.world_mut().spawn((
+                RgbLed::default(),
+                FastExpansionDevice {
+                    expansion_address: "48",
+                    port: 0,
+                    index,
+                },
+            ));
+        }
+        app.add_systems(Update, (resync_leds, led_change_listener).chain());
+        app.update();
+        app.update();
+        app.update();
+
+        // Only the LED frames matter, not whether a fade time was sent with them
+        let frames = port
+            .written()
+            .into_iter()
+            .filter(|frame| frame.starts_with("RS@"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            frames,
+            vec![
+                "RS@480:0ffffff,1ffffff,2ffffff,3ffffff",
+                "RS@480:2ffffff,3ffffff",
+                "RS@480:0ffffff,1ffffff",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_fades_only_the_color_it_was_set_for() {
+        let port = MockPort::default();
+        let mut app = App::new();
+        app.insert_resource(ExpPort(Arc::new(Mutex::new(Box::new(port.clone())))));
+        app.init_resource::<LedFrames>();
+        app.insert_resource(PinballConfig {
+            led_luminance_scale: 1.0,
+        });
+        app.insert_resource(ExpansionLedConfig {
+            update_interval: Duration::from_millis(100),
+            max_bytes_per_update: 2048,
+            resync_period: None,
+        });
+        let led = app
+            .world_mut()
+            .spawn((
+                RgbLed::default(),
+                FastExpansionDevice {
+                    expansion_address: "48",
+                    port: 0,
+                    index: 0,
+                },
+            ))
+            .id();
+        app.add_systems(Update, led_change_listener);
+        app.update();
+        let sent = port.written().len();
+
+        // The last stage of an animation is handed to the hardware...
+        let mut component = app.world_mut().get_mut::<RgbLed>(led).unwrap();
+        component.color = Srgba::rgb(1., 0., 0.);
+        component.fade = Duration::from_millis(250);
+        app.update();
+        assert_eq!(port.written()[sent..], ["RF@48:FA", "RS@480:0ff0000"]);
+        let sent = port.written().len();
+
+        // ...and the next color is set directly, so the board stops fading
+        app.world_mut().get_mut::<RgbLed>(led).unwrap().color = Srgba::rgb(0., 0., 1.);
+        app.update();
+        assert_eq!(port.written()[sent..], ["RF@48:0", "RS@480:00000ff"]);
+    }
+
+    #[test]
+    fn it_lays_out_mixed_ports() {
+        let led = |port, index, led_type| LedDefinition {
+            port,
+            index,
+            led_type,
+            ..Default::default()
+        };
+        let plugin = ExpansionLeds {
+            leds: vec![
+                led(0, 0, None),
+                led(0, 1, Some(LedType::Rgb)),
+                // Index 2 isn't defined, so is the port's RGBW
+                led(0, 3, None),
+                led(0, 4, Some(LedType::Rgb)),
+                led(0, 5, None),
+                led(1, 2, None),
+            ],
+            port_calibration: vec![PortCalibration {
+                port: 0,
+                led_type: LedType::Rgbw,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let layouts = plugin
+            .leds
+            .iter()
+            .map(|led| plugin.layout(led))
+            .map(|layout| (layout.led_type, layout.offset))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            layouts,
+            vec![
+                (LedType::Rgbw, 0),
+                (LedType::Rgb, 4),
+                (LedType::Rgbw, 11),
+                (LedType::Rgb, 15),
+                (LedType::Rgbw, 18),
+                (LedType::Rgb, 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_bounds_leds_to_the_port() {
+        let led = |index| LedDefinition {
+            index,
+            ..Default::default()
+        };
+        let plugin = |led_type| ExpansionLeds {
+            leds: vec![led(191), led(192), led(255)],
+            port_calibration: vec![PortCalibration {
+                led_type,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        // An RGBW port runs out of indexes before the LED index does
+        let fits = |plugin: ExpansionLeds| {
+            plugin
+                .leds
+                .iter()
+                .map(|led| plugin.layout(led).fits())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(fits(plugin(LedType::Rgb)), vec![true, true, true]);
+        assert_eq!(fits(plugin(LedType::Rgbw)), vec![true, false, false]);
+    }
 
     #[test]
     fn it_converts_single_digits() {
-        let hex = hsl_to_hex(Srgba::rgb(0., 0., 0.));
+        let hex = rgb_to_hex(CalibrationTable::default().apply(Srgba::rgb(0., 0., 0.), 1.));
         assert_eq!(hex, "000000".to_string());
     }
 
     #[test]
     fn it_makes_white() {
-        let hex = hsl_to_hex(Srgba::rgb(1., 1., 1.));
+        let hex = rgb_to_hex(CalibrationTable::default().apply(Srgba::rgb(1., 1., 1.), 1.));
         assert_eq!(hex, "ffffff".to_string());
     }
 }
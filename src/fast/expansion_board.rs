@@ -64,6 +64,21 @@ impl ExpansionBoard {
             } => "8B",
         }
     }
+
+    /// Finds the board at an address, e.g. `b4`, ignoring case
+    pub fn from_address(address: &str) -> Option<Self> {
+        let jumpers = [(false, false), (true, false), (false, true), (true, true)];
+        let boards = jumpers.into_iter().flat_map(|(jumper_0, jumper_1)| {
+            [
+                Self::FpExp0071 { jumper_0, jumper_1 },
+                Self::FpExp0081 { jumper_0, jumper_1 },
+                Self::FpExp0091 { jumper_0, jumper_1 },
+            ]
+        });
+        std::iter::once(Self::Neutron)
+            .chain(boards)
+            .find(|board| board.as_str().eq_ignore_ascii_case(address))
+    }
 }
 
 impl std::default::Default for ExpansionBoard {
@@ -0,0 +1,54 @@
+/// Splits data read from a FAST serial port into `\r` terminated messages.
+///
+/// A single read can contain several messages, or only part of one, so any trailing
+/// partial message is kept until the rest of it arrives on a later read.
+#[derive(Debug, Default, Clone)]
+pub struct LineBuffer {
+    pending: String,
+}
+
+impl LineBuffer {
+    /// Appends newly read data and returns every message it completes
+    pub fn push(&mut self, data: &str) -> Vec<String> {
+        self.pending.push_str(data);
+        let Some(end) = self.pending.rfind('\r') else {
+            return vec![];
+        };
+
+        let remainder = self.pending.split_off(end + 1);
+        let complete = std::mem::replace(&mut self.pending, remainder);
+        complete
+            .split('\r')
+            .map(|line| line.trim_matches('\n'))
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_splits_batched_messages() {
+        let mut buffer = LineBuffer::default();
+        let lines = buffer.push("-L:1A\r/L:1A\r-L:03\r");
+        assert_eq!(lines, vec!["-L:1A", "/L:1A", "-L:03"]);
+    }
+
+    #[test]
+    fn it_keeps_partial_messages_between_reads() {
+        let mut buffer = LineBuffer::default();
+        assert!(buffer.push("-L:").is_empty());
+        assert_eq!(buffer.push("1A\r/L"), vec!["-L:1A"]);
+        assert_eq!(buffer.push(":1A\r"), vec!["/L:1A"]);
+    }
+
+    #[test]
+    fn it_ignores_empty_and_newline_terminated_messages() {
+        let mut buffer = LineBuffer::default();
+        let lines = buffer.push("\r\nID:NET FP-CPU-2000 02.13\r\n\r");
+        assert_eq!(lines, vec!["ID:NET FP-CPU-2000 02.13"]);
+    }
+}
@@ -1,20 +1,36 @@
+use bevy::prelude::*;
+use std::fmt;
+
+use super::{
+    NodeInfo,
+    command::{SwitchConfig, SwitchMode},
+    controller::{FastController, RetroPlatform},
+};
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 #[allow(dead_code)]
 pub enum IoBoard {
     Fast3208 {
-        switches: Vec<Option<&'static str>>,
+        switches: Vec<Option<IoSwitch>>,
         coils: Vec<Option<&'static str>>,
     },
     Fast1616 {
-        switches: Vec<Option<&'static str>>,
+        switches: Vec<Option<IoSwitch>>,
         coils: Vec<Option<&'static str>>,
     },
     Fast0804 {
-        switches: Vec<Option<&'static str>>,
+        switches: Vec<Option<IoSwitch>>,
         coils: Vec<Option<&'static str>>,
     },
     CabinetIO {
-        switches: Vec<Option<&'static str>>,
+        switches: Vec<Option<IoSwitch>>,
+        coils: Vec<Option<&'static str>>,
+    },
+    /// The switch matrix and solenoids driven by a Retro controller. Ports are numbered
+    /// with `RetroPlatform::matrix_switch` and friends.
+    Retro {
+        platform: RetroPlatform,
+        switches: Vec<Option<IoSwitch>>,
         coils: Vec<Option<&'static str>>,
     },
 }
@@ -28,6 +44,7 @@ impl IoBoard {
             Self::Fast1616 { .. } => 16,
             Self::Fast0804 { .. } => 4,
             Self::CabinetIO { .. } => 8,
+            Self::Retro { platform, .. } => platform.coil_port_count(),
         }
     }
 
@@ -38,6 +55,382 @@ impl IoBoard {
             Self::Fast1616 { .. } => 16,
             Self::Fast0804 { .. } => 8,
             Self::CabinetIO { .. } => 24,
+            Self::Retro { platform, .. } => platform.switch_port_count(),
+        }
+    }
+
+    /// Gets the model the board reports to `NN:`, less its revision
+    pub fn product(&self) -> &'static str {
+        match self {
+            Self::Fast3208 { .. } => "FP-I/O-3208",
+            Self::Fast1616 { .. } => "FP-I/O-1616",
+            Self::Fast0804 { .. } => "FP-I/O-0804",
+            Self::CabinetIO { .. } => "FP-I/O-0024",
+            Self::Retro { platform, .. } => FastController::Retro(*platform).product(),
+        }
+    }
+
+    /// Gets the switches on this board, by port
+    pub fn switches(&self) -> &[Option<IoSwitch>] {
+        match self {
+            Self::Fast3208 { switches, .. }
+            | Self::Fast1616 { switches, .. }
+            | Self::Fast0804 { switches, .. }
+            | Self::CabinetIO { switches, .. }
+            | Self::Retro { switches, .. } => switches,
+        }
+    }
+
+    fn matches(&self, node: &NodeInfo) -> bool {
+        node.model.starts_with(self.product())
+    }
+}
+
+/// A switch on an IO board, and how the board should report it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoSwitch {
+    pub name: &'static str,
+    /// The circuit is closed while the switch isn't activated, e.g. the optos in a trough.
+    /// The board inverts these so they are reported closed when activated.
+    pub normally_closed: bool,
+    /// Time in ms the switch must be closed before it is reported closed
+    pub debounce_close: u8,
+    /// Time in ms the switch must be open before it is reported open
+    pub debounce_open: u8,
+    /// Whether the board reports changes to the switch
+    pub report: bool,
+}
+
+impl Default for IoSwitch {
+    fn default() -> Self {
+        Self {
+            name: "",
+            normally_closed: false,
+            debounce_close: 2,
+            debounce_open: 2,
+            report: true,
+        }
+    }
+}
+
+impl IoSwitch {
+    /// The `SL:` settings of this switch, once it's numbered
+    pub fn config(&self, switch: u16) -> SwitchConfig {
+        let mode = match (self.report, self.normally_closed) {
+            (false, _) => SwitchMode::Disabled,
+            (true, false) => SwitchMode::Normal,
+            (true, true) => SwitchMode::Inverted,
+        };
+        SwitchConfig {
+            switch,
+            mode,
+            debounce_close: self.debounce_close,
+            debounce_open: self.debounce_open,
+        }
+    }
+}
+
+/// A switch or driver port on an IO board
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IoAddress {
+    /// Position of the IO board in the loop. The board nearest the Neutron = `0`
+    pub board: usize,
+    pub port: u8,
+}
+
+/// The IO boards connected to the IO/NET loop, in loop order
+#[derive(Resource, Debug, Default, Clone, PartialEq, Eq)]
+pub struct IoNetLoop(pub Vec<IoBoard>);
+
+impl IoNetLoop {
+    /// Gets the FAST switch number of a port on the board at the given loop position.
+    /// Switches are numbered sequentially across boards in loop order.
+    pub fn switch_number(&self, board: usize, port: u8) -> Option<u16> {
+        let target = self.0.get(board)?;
+        if port >= target.switch_port_count() {
+            return None;
+        }
+        let offset: u16 = self.0[..board]
+            .iter()
+            .map(|b| b.switch_port_count() as u16)
+            .sum();
+        Some(offset + port as u16)
+    }
+
+    /// Gets the FAST driver number of a port on the board at the given loop position.
+    /// Drivers are numbered sequentially across boards in loop order.
+    pub fn driver_number(&self, board: usize, port: u8) -> Option<u16> {
+        let target = self.0.get(board)?;
+        if port >= target.coil_port_count() {
+            return None;
+        }
+        let offset: u16 = self.0[..board]
+            .iter()
+            .map(|b| b.coil_port_count() as u16)
+            .sum();
+        Some(offset + port as u16)
+    }
+
+    /// Gets the `SL:` settings of every switch defined on the boards, in switch order.
+    /// Switches past the last port of their board are left out.
+    pub fn switch_configs(&self) -> Vec<SwitchConfig> {
+        let mut configs = vec![];
+        for (board, io_board) in self.0.iter().enumerate() {
+            for (port, switch) in io_board.switches().iter().enumerate() {
+                let number = self.switch_number(board, port as u8);
+                if let Some((switch, number)) = switch.as_ref().zip(number) {
+                    configs.push(switch.config(number));
+                }
+            }
+        }
+        configs
+    }
+
+    /// Compares the nodes which answered `NN:` with the configured boards
+    pub fn verify(&self, nodes: &[NodeInfo]) -> Vec<LoopMismatch> {
+        let found = |position: usize| nodes.iter().find(|n| n.node as usize == position);
+        let count = self
+            .0
+            .len()
+            .max(nodes.iter().map(|n| n.node as usize + 1).max().unwrap_or(0));
+        let mut swapped = vec![];
+        let mut mismatches = vec![];
+        for board in 0..count {
+            if swapped.contains(&board) {
+                continue;
+            }
+            match (self.0.get(board), found(board)) {
+                (Some(expected), Some(node)) if expected.matches(node) => {}
+                (Some(expected), Some(node)) => {
+                    let other = (board + 1..count).find(|other| {
+                        !swapped.contains(other)
+                            && self.0.get(*other).is_some_and(|e| e.matches(node))
+                            && found(*other).is_some_and(|n| expected.matches(n))
+                    });
+                    match other {
+                        Some(second) => {
+                            swapped.push(second);
+                            mismatches.push(LoopMismatch::Swapped {
+                                first: board,
+                                second,
+                            });
+                        }
+                        None => mismatches.push(LoopMismatch::Wrong {
+                            board,
+                            expected: expected.product(),
+                            found: node.model.clone(),
+                        }),
+                    }
+                }
+                (Some(expected), None) => mismatches.push(LoopMismatch::Missing {
+                    board,
+                    expected: expected.product(),
+                }),
+                (None, Some(node)) => mismatches.push(LoopMismatch::Unexpected {
+                    board,
+                    found: node.model.clone(),
+                }),
+                (None, None) => {}
+            }
+        }
+        mismatches
+    }
+}
+
+/// Sent once every node on the IO loop has answered `NN:` after connecting
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct IoLoopChecked {
+    /// The boards which answered, in loop order
+    pub nodes: Vec<NodeInfo>,
+    /// Empty when the loop matches the configured boards
+    pub mismatches: Vec<LoopMismatch>,
+}
+
+/// A difference between the configured IO boards and the ones found on the loop
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoopMismatch {
+    /// No board answered at a configured position
+    Missing {
+        board: usize,
+        expected: &'static str,
+    },
+    /// A board answered past the end of the configured loop
+    Unexpected { board: usize, found: String },
+    /// A different board answered at a configured position
+    Wrong {
+        board: usize,
+        expected: &'static str,
+        found: String,
+    },
+    /// Two boards are in each other's positions
+    Swapped { first: usize, second: usize },
+}
+
+impl fmt::Display for LoopMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Missing { board, expected } => {
+                write!(f, "No board found at IO board {board}, expected {expected}")
+            }
+            Self::Unexpected { board, found } => {
+                write!(
+                    f,
+                    "Found {found} at IO board {board}, which isn't configured"
+                )
+            }
+            Self::Wrong {
+                board,
+                expected,
+                found,
+            } => write!(f, "Found {found} at IO board {board}, expected {expected}"),
+            Self::Swapped { first, second } => {
+                write!(f, "IO boards {first} and {second} are swapped")
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board_loop() -> IoNetLoop {
+        IoNetLoop(vec![
+            IoBoard::CabinetIO {
+                switches: vec![],
+                coils: vec![],
+            },
+            IoBoard::Fast3208 {
+                switches: vec![],
+                coils: vec![],
+            },
+            IoBoard::Fast0804 {
+                switches: vec![],
+                coils: vec![],
+            },
+        ])
+    }
+
+    #[test]
+    fn it_numbers_switches_in_loop_order() {
+        let io_loop = board_loop();
+        assert_eq!(io_loop.switch_number(0, 0), Some(0));
+        assert_eq!(io_loop.switch_number(1, 0), Some(24));
+        assert_eq!(io_loop.switch_number(2, 7), Some(63));
+    }
+
+    #[test]
+    fn it_numbers_drivers_in_loop_order() {
+        let io_loop = board_loop();
+        assert_eq!(io_loop.driver_number(0, 7), Some(7));
+        assert_eq!(io_loop.driver_number(1, 0), Some(8));
+        assert_eq!(io_loop.driver_number(2, 3), Some(19));
+        assert_eq!(io_loop.driver_number(2, 4), None);
+    }
+
+    #[test]
+    fn it_configures_defined_switches() {
+        let trough = IoSwitch {
+            name: "trough_1",
+            normally_closed: true,
+            debounce_close: 20,
+            ..Default::default()
+        };
+        let io_loop = IoNetLoop(vec![
+            IoBoard::CabinetIO {
+                switches: vec![None, Some(IoSwitch::default())],
+                coils: vec![],
+            },
+            IoBoard::Fast3208 {
+                switches: vec![
+                    Some(trough),
+                    Some(IoSwitch {
+                        report: false,
+                        ..trough
+                    }),
+                ],
+                coils: vec![],
+            },
+        ]);
+        let config = |switch, mode, debounce_close| SwitchConfig {
+            switch,
+            mode,
+            debounce_close,
+            debounce_open: 2,
+        };
+        assert_eq!(
+            io_loop.switch_configs(),
+            vec![
+                config(1, SwitchMode::Normal, 2),
+                config(24, SwitchMode::Inverted, 20),
+                config(25, SwitchMode::Disabled, 20),
+            ]
+        );
+    }
+
+    fn node(node: u8, model: &str) -> NodeInfo {
+        NodeInfo {
+            node,
+            model: model.to_string(),
+            firmware: "01.05".to_string(),
+            drivers: 8,
+            switches: 32,
+        }
+    }
+
+    #[test]
+    fn it_verifies_the_loop() {
+        let io_loop = board_loop();
+        let nodes = [
+            node(0, "FP-I/O-0024-1"),
+            node(1, "FP-I/O-3208-2"),
+            node(2, "FP-I/O-0804-1"),
+        ];
+        assert_eq!(io_loop.verify(&nodes), vec![]);
+
+        // Cabinet and 3208 the other way around, and the 0804 missing
+        let nodes = [node(0, "FP-I/O-3208-2"), node(1, "FP-I/O-0024-1")];
+        assert_eq!(
+            io_loop.verify(&nodes),
+            vec![
+                LoopMismatch::Swapped {
+                    first: 0,
+                    second: 1
+                },
+                LoopMismatch::Missing {
+                    board: 2,
+                    expected: "FP-I/O-0804"
+                },
+            ]
+        );
+
+        // A 1616 where the 0804 should be, and an extra board after it
+        let nodes = [
+            node(0, "FP-I/O-0024-1"),
+            node(1, "FP-I/O-3208-2"),
+            node(2, "FP-I/O-1616-2"),
+            node(3, "FP-I/O-0804-1"),
+        ];
+        assert_eq!(
+            io_loop.verify(&nodes),
+            vec![
+                LoopMismatch::Wrong {
+                    board: 2,
+                    expected: "FP-I/O-0804",
+                    found: "FP-I/O-1616-2".to_string()
+                },
+                LoopMismatch::Unexpected {
+                    board: 3,
+                    found: "FP-I/O-0804-1".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_rejects_out_of_range_switches() {
+        let io_loop = board_loop();
+        assert_eq!(io_loop.switch_number(0, 24), None);
+        assert_eq!(io_loop.switch_number(3, 0), None);
+    }
+}
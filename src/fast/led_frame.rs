@@ -0,0 +1,267 @@
+use bevy::prelude::*;
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::Duration,
+};
+
+use super::exp_led_port::{FastExpansionDevice, LedLayout, rgb_to_hex};
+
+/// Expansion address, port and fade time of a group of LEDs
+type LedGroupKey = (&'static str, u8, Duration);
+
+/// Calibrated LED colors waiting to be sent on the EXP bus, grouped per expansion board,
+/// port and fade time
+///
+/// The boards address a port three bytes at a time, whatever LEDs are attached, so each
+/// port's bytes are kept and every index an LED's bytes touch is sent. On an RGB port
+/// that's one index per LED; an RGBW LED spans two.
+///
+/// Only the latest bytes of each index are sent, so an LED which changes again before it
+/// is sent costs nothing extra. Groups are sent oldest first, so a port which changes
+/// every tick can't starve the others when the byte budget runs out.
+#[derive(Resource, Debug, Default, Clone)]
+pub struct LedFrames {
+    /// Every byte queued so far, per expansion address and port
+    ports: HashMap<(&'static str, u8), Vec<u8>>,
+    groups: Vec<(LedGroupKey, BTreeSet<u8>)>,
+    /// Fade time last sent to each board. A board without one fades in zero time.
+    fades: HashMap<&'static str, Duration>,
+}
+
+impl LedFrames {
+    /// Queue a color to be sent, replacing any color still waiting for the same indexes
+    pub fn queue(
+        &mut self,
+        led: &FastExpansionDevice,
+        layout: &LedLayout,
+        color: [u8; 3],
+        fade: Duration,
+    ) {
+        let bytes = layout.led_type.encode(color);
+        let end = layout.offset + bytes.len();
+        let port_bytes = self
+            .ports
+            .entry((led.expansion_address, led.port))
+            .or_default();
+        if port_bytes.len() < end {
+            port_bytes.resize(end.div_ceil(3) * 3, 0);
+        }
+        port_bytes[layout.offset..end].copy_from_slice(&bytes);
+        let indexes = (layout.offset / 3) as u8..=((end - 1) / 3) as u8;
+
+        let key = (led.expansion_address, led.port, fade);
+        for ((address, port, _), queued) in self.groups.iter_mut() {
+            if (*address, *port) == (led.expansion_address, led.port) {
+                queued.retain(|index| !indexes.contains(index));
+            }
+        }
+        self.groups
+            .retain(|(group, queued)| *group == key || !queued.is_empty());
+        match self.groups.iter_mut().find(|(group, _)| *group == key) {
+            Some((_, queued)) => queued.extend(indexes),
+            None => self.groups.push((key, indexes.collect())),
+        }
+    }
+
+    /// Forget the fade time sent to each board, e.g. after the boards have reset
+    pub fn reset_fades(&mut self) {
+        self.fades.clear();
+    }
+
+    /// Take as many frames as fit in the byte budget: an `RS` frame per group, preceded by
+    /// an `RF` whenever the group's fade time differs from the one its board has. LEDs
+    /// which don't fit are kept for the next call. At least one LED is always taken, so a
+    /// budget smaller than a single frame slows updates down rather than stalling them.
+    pub fn take(&mut self, budget: usize) -> Vec<String> {
+        let mut frames: Vec<String> = vec![];
+        let mut remaining = budget;
+        while let Some(((address, port, fade), queued)) = self.groups.first_mut() {
+            let port_bytes = &self.ports[&(*address, *port)];
+
+            // The fade time applies to the whole board until it is changed
+            let fade_frame = match self.fades.get(address).copied().unwrap_or_default() == *fade {
+                true => None,
+                false => Some(format!("RF@{address}:{:X}", fade.as_millis())),
+            };
+
+            // Each frame ends with a `\r`
+            let mut frame = format!("RS@{address}{port}:");
+            let mut length = frame.len() + 1 + fade_frame.as_ref().map_or(0, |f| f.len() + 1);
+            let mut sent = vec![];
+            for index in queued.iter() {
+                let start = *index as usize * 3;
+                let color = [
+                    port_bytes[start],
+                    port_bytes[start + 1],
+                    port_bytes[start + 2],
+                ];
+                let item = format!("{index}{}", rgb_to_hex(color));
+                let separator = usize::from(!sent.is_empty());
+                let fits = length + separator + item.len() <= remaining;
+                let first = frames.is_empty() && sent.is_empty();
+                if !fits && !first {
+                    break;
+                }
+                if separator == 1 {
+                    frame.push(',');
+                }
+                frame.push_str(&item);
+                length += separator + item.len();
+                sent.push(*index);
+            }
+            if sent.is_empty() {
+                break;
+            }
+
+            if let Some(fade_frame) = fade_frame {
+                self.fades.insert(*address, *fade);
+                frames.push(fade_frame);
+            }
+            frames.push(frame);
+            remaining = remaining.saturating_sub(length);
+            for index in sent {
+                queued.remove(&index);
+            }
+            if queued.is_empty() {
+                self.groups.remove(0);
+            }
+        }
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fast::calibration::LedType;
+
+    fn led(expansion_address: &'static str, port: u8, index: u8) -> FastExpansionDevice {
+        FastExpansionDevice {
+            expansion_address,
+            port,
+            index,
+        }
+    }
+
+    fn rgb(index: u8) -> LedLayout {
+        LedLayout::rgb(index)
+    }
+
+    #[test]
+    fn it_groups_leds_per_board_and_port() {
+        let mut frames = LedFrames::default();
+        frames.queue(&led("48", 0, 2), &rgb(2), [255, 0, 0], Duration::ZERO);
+        frames.queue(&led("B4", 1, 0), &rgb(0), [0, 0, 255], Duration::ZERO);
+        frames.queue(&led("48", 0, 0), &rgb(0), [0, 255, 0], Duration::ZERO);
+
+        assert_eq!(
+            frames.take(usize::MAX),
+            vec!["RS@480:000ff00,2ff0000", "RS@B41:00000ff"]
+        );
+        assert!(frames.take(usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn it_coalesces_repeated_changes() {
+        let mut frames = LedFrames::default();
+        frames.queue(&led("48", 0, 5), &rgb(5), [255, 0, 0], Duration::ZERO);
+        frames.queue(&led("48", 0, 5), &rgb(5), [255, 255, 255], Duration::ZERO);
+        assert_eq!(frames.take(usize::MAX), vec!["RS@480:5ffffff"]);
+    }
+
+    #[test]
+    fn it_keeps_what_doesnt_fit_in_the_budget() {
+        let mut frames = LedFrames::default();
+        for index in 0..4 {
+            frames.queue(
+                &led("48", 0, index),
+                &rgb(index),
+                [255, 0, 0],
+                Duration::ZERO,
+            );
+        }
+        frames.queue(&led("B4", 1, 0), &rgb(0), [0, 0, 255], Duration::ZERO);
+
+        // 8 bytes for the header, then 7 for the first LED and 8 for each after, counting
+        // the `\r` which ends the frame
+        assert_eq!(frames.take(23), vec!["RS@480:0ff0000,1ff0000"]);
+        assert_eq!(
+            frames.take(usize::MAX),
+            vec!["RS@480:2ff0000,3ff0000", "RS@B41:00000ff"]
+        );
+    }
+
+    #[test]
+    fn it_always_sends_at_least_one_led() {
+        let mut frames = LedFrames::default();
+        frames.queue(&led("48", 0, 0), &rgb(0), [255, 0, 0], Duration::ZERO);
+        frames.queue(&led("48", 0, 1), &rgb(1), [255, 0, 0], Duration::ZERO);
+        assert_eq!(frames.take(0), vec!["RS@480:0ff0000"]);
+        assert_eq!(frames.take(0), vec!["RS@480:1ff0000"]);
+    }
+
+    #[test]
+    fn it_sets_the_fade_time_before_each_group() {
+        let fade = Duration::from_millis(250);
+        let mut frames = LedFrames::default();
+        frames.queue(&led("48", 0, 0), &rgb(0), [255, 0, 0], fade);
+        frames.queue(&led("48", 0, 1), &rgb(1), [255, 0, 0], fade);
+        frames.queue(&led("48", 1, 0), &rgb(0), [255, 0, 0], fade);
+        // Moves to a group without a fade
+        frames.queue(&led("48", 0, 1), &rgb(1), [0, 0, 255], Duration::ZERO);
+
+        assert_eq!(
+            frames.take(usize::MAX),
+            vec![
+                "RF@48:FA",
+                "RS@480:0ff0000",
+                "RS@481:0ff0000",
+                "RF@48:0",
+                "RS@480:10000ff"
+            ]
+        );
+
+        // The board keeps its fade time, so it's only sent again once it changes...
+        frames.queue(&led("48", 0, 2), &rgb(2), [255, 0, 0], Duration::ZERO);
+        assert_eq!(frames.take(usize::MAX), vec!["RS@480:2ff0000"]);
+        frames.queue(&led("48", 0, 2), &rgb(2), [0, 0, 0], fade);
+        assert_eq!(frames.take(usize::MAX), vec!["RF@48:FA", "RS@480:2000000"]);
+
+        // ...or the board has reset
+        frames.reset_fades();
+        frames.queue(&led("48", 0, 3), &rgb(3), [255, 0, 0], fade);
+        assert_eq!(frames.take(usize::MAX), vec!["RF@48:FA", "RS@480:3ff0000"]);
+    }
+
+    #[test]
+    fn it_sends_every_index_an_rgbw_led_spans() {
+        let layout = |led_type, offset| LedLayout { led_type, offset };
+        let mut frames = LedFrames::default();
+        // An RGB LED, then two RGBW LEDs starting 3 and 7 bytes into the port
+        frames.queue(
+            &led("48", 0, 0),
+            &layout(LedType::Rgb, 0),
+            [255, 0, 0],
+            Duration::ZERO,
+        );
+        frames.queue(
+            &led("48", 0, 1),
+            &layout(LedType::Rgbw, 3),
+            [255, 255, 255],
+            Duration::ZERO,
+        );
+        assert_eq!(
+            frames.take(usize::MAX),
+            vec!["RS@480:0ff0000,1000000,2ff0000"]
+        );
+
+        // The second index is shared, so it carries the white of the LED before as well
+        frames.queue(
+            &led("48", 0, 2),
+            &layout(LedType::Rgbw, 7),
+            [0, 0, 255],
+            Duration::ZERO,
+        );
+        assert_eq!(frames.take(usize::MAX), vec!["RS@480:2ff0000,3ff0000"]);
+    }
+}
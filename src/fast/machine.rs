@@ -0,0 +1,203 @@
+use std::str::FromStr;
+
+use super::{
+    controller::{FastController, RetroPlatform},
+    expansion_board::ExpansionBoard,
+    io_board::{IoBoard, IoNetLoop},
+};
+
+/// MachineDescription - The hardware of a machine, as read by the `fast-emu` emulator
+///
+/// One item per line, with `#` starting a comment:
+///
+/// ```text
+/// controller neutron     # neutron (the default), nano, sys11, wpc89 or wpc95
+/// io cabinet             # IO boards in loop order: cabinet, 3208, 1616 or 0804
+/// io 3208
+/// switch 0 2 start       # board, port and name of a switch
+/// driver 1 0 left_flip   # board, port and name of a driver
+/// leds 48 0 32           # expansion address, port and number of LEDs
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MachineDescription {
+    pub controller: FastController,
+    /// IO boards on the IO/NET loop, in loop order
+    pub io_boards: Vec<IoBoard>,
+    /// Names of switches, by FAST switch number
+    pub switches: Vec<(u16, String)>,
+    /// Names of drivers, by FAST driver number
+    pub drivers: Vec<(u16, String)>,
+    pub led_ports: Vec<LedPort>,
+}
+
+/// A port of LEDs on an expansion board
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedPort {
+    pub expansion_address: &'static str,
+    pub port: u8,
+    pub count: u8,
+}
+
+impl MachineDescription {
+    /// Total number of switches across every IO board
+    pub fn switch_count(&self) -> usize {
+        self.io_boards
+            .iter()
+            .map(|board| board.switch_port_count() as usize)
+            .sum()
+    }
+
+    /// Finds a switch by name or by number
+    pub fn switch(&self, name: &str) -> Option<u16> {
+        match self.switches.iter().find(|(_, n)| n == name) {
+            Some((number, _)) => Some(*number),
+            None => name.parse().ok(),
+        }
+    }
+}
+
+impl FromStr for MachineDescription {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut machine = MachineDescription::default();
+        for (line_number, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let invalid = || format!("Line {}: unable to read `{}`", line_number + 1, line.trim());
+            let io_loop = IoNetLoop(machine.io_boards.clone());
+            match words.as_slice() {
+                [] => {}
+                // A Retro's own switches and drivers are numbered before any IO boards
+                ["controller", kind] if machine.io_boards.is_empty() => {
+                    machine.controller = controller(kind).ok_or_else(invalid)?;
+                    if let FastController::Retro(platform) = machine.controller {
+                        let (switches, coils) = (vec![], vec![]);
+                        machine.io_boards.push(IoBoard::Retro {
+                            platform,
+                            switches,
+                            coils,
+                        });
+                    }
+                }
+                ["io", kind] => machine
+                    .io_boards
+                    .push(io_board(kind).ok_or_else(invalid)?),
+                ["switch", board, port, name] => {
+                    let number = board
+                        .parse()
+                        .ok()
+                        .zip(port.parse().ok())
+                        .and_then(|(board, port)| io_loop.switch_number(board, port))
+                        .ok_or_else(invalid)?;
+                    machine.switches.push((number, name.to_string()));
+                }
+                ["driver", board, port, name] => {
+                    let number = board
+                        .parse()
+                        .ok()
+                        .zip(port.parse().ok())
+                        .and_then(|(board, port)| io_loop.driver_number(board, port))
+                        .ok_or_else(invalid)?;
+                    machine.drivers.push((number, name.to_string()));
+                }
+                ["leds", address, port, count] => machine.led_ports.push(LedPort {
+                    expansion_address: ExpansionBoard::from_address(address)
+                        .ok_or_else(invalid)?
+                        .as_str(),
+                    port: port.parse().map_err(|_| invalid())?,
+                    count: count.parse().map_err(|_| invalid())?,
+                }),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(machine)
+    }
+}
+
+fn controller(kind: &str) -> Option<FastController> {
+    match kind {
+        "neutron" => Some(FastController::Neutron),
+        "nano" => Some(FastController::Nano),
+        "sys11" => Some(FastController::Retro(RetroPlatform::Sys11)),
+        "wpc89" => Some(FastController::Retro(RetroPlatform::Wpc89)),
+        "wpc95" => Some(FastController::Retro(RetroPlatform::Wpc95)),
+        _ => None,
+    }
+}
+
+fn io_board(kind: &str) -> Option<IoBoard> {
+    let (switches, coils) = (vec![], vec![]);
+    match kind {
+        "cabinet" => Some(IoBoard::CabinetIO { switches, coils }),
+        "3208" => Some(IoBoard::Fast3208 { switches, coils }),
+        "1616" => Some(IoBoard::Fast1616 { switches, coils }),
+        "0804" => Some(IoBoard::Fast0804 { switches, coils }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_a_machine_description() {
+        let machine = "
+            # cabinet first
+            io cabinet
+            io 3208
+            switch 0 2 start
+            switch 1 4 left_sling   # after the 24 cabinet switches
+            driver 1 3 left_flipper
+            leds 48 0 32
+            leds b4 1 8
+        "
+        .parse::<MachineDescription>()
+        .unwrap();
+
+        assert_eq!(machine.io_boards.len(), 2);
+        assert_eq!(machine.switch_count(), 56);
+        assert_eq!(machine.switch("left_sling"), Some(28));
+        assert_eq!(machine.switch("12"), Some(12));
+        assert_eq!(machine.drivers, vec![(11, "left_flipper".to_string())]);
+        assert_eq!(
+            machine.led_ports,
+            vec![
+                LedPort {
+                    expansion_address: "48",
+                    port: 0,
+                    count: 32
+                },
+                LedPort {
+                    expansion_address: "B4",
+                    port: 1,
+                    count: 8
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn it_numbers_retro_switches_first() {
+        let machine = "controller wpc95\nio 0804\nswitch 1 0 shooter"
+            .parse::<MachineDescription>()
+            .unwrap();
+        assert_eq!(
+            machine.controller,
+            FastController::Retro(RetroPlatform::Wpc95)
+        );
+        assert_eq!(machine.switch("shooter"), Some(72));
+    }
+
+    #[test]
+    fn it_rejects_unknown_lines() {
+        let result = "io cabinet\nswitch 3 0 start".parse::<MachineDescription>();
+        assert_eq!(result, Err("Line 2: unable to read `switch 3 0 start`".to_string()));
+        let result = "leds 12 0 8".parse::<MachineDescription>();
+        assert_eq!(
+            result,
+            Err("Line 1: unable to read `leds 12 0 8`".to_string())
+        );
+    }
+}
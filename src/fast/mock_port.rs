@@ -0,0 +1,78 @@
+use super::transport::Transport;
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::{Arc, Mutex},
+};
+
+/// An in-memory transport for tests. Clones share the same buffers, so a test can
+/// keep a clone to inspect what was written and queue up data to be read.
+#[derive(Debug, Default, Clone)]
+pub struct MockPort {
+    written: Arc<Mutex<Vec<u8>>>,
+    unread: Arc<Mutex<VecDeque<u8>>>,
+    disconnected: Arc<Mutex<bool>>,
+}
+
+impl MockPort {
+    /// Queues data to be read from the port
+    pub fn respond(&self, data: &str) {
+        self.unread.lock().unwrap().extend(data.as_bytes());
+    }
+
+    /// Makes the port behave as if the device was unplugged
+    pub fn disconnect(&self) {
+        *self.disconnected.lock().unwrap() = true;
+    }
+
+    fn check_connected(&self) -> io::Result<()> {
+        match *self.disconnected.lock().unwrap() {
+            true => Err(io::ErrorKind::BrokenPipe.into()),
+            false => Ok(()),
+        }
+    }
+
+    /// Everything written to the port so far, split into messages
+    pub fn written(&self) -> Vec<String> {
+        let written = self.written.lock().unwrap();
+        String::from_utf8_lossy(&written)
+            .split('\r')
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+impl Read for MockPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.check_connected()?;
+        let mut unread = self.unread.lock().unwrap();
+        if unread.is_empty() {
+            // Like a real port, reading with nothing available times out
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        let count = buf.len().min(unread.len());
+        for (i, byte) in unread.drain(..count).enumerate() {
+            buf[i] = byte;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for MockPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.check_connected()?;
+        self.written.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for MockPort {
+    fn is_connected(&self) -> bool {
+        self.check_connected().is_ok()
+    }
+}
@@ -1,15 +1,61 @@
+mod calibration;
+mod capture;
+mod command;
+mod connection;
+mod controller;
+mod drivers;
 mod exp_led_port;
 mod expansion_board;
+mod framing;
 mod io_board;
+mod led_frame;
+mod machine;
+#[cfg(test)]
+mod mock_port;
 mod neutron;
 mod parser;
+mod replay;
+mod rules;
 mod serial;
+mod servo;
+mod simulator;
+mod stepper;
+mod switches;
+mod transport;
+mod watchdog;
 
 pub mod resources;
 
+pub use calibration::{
+    CalibrationTable, ChannelOrder, LedCalibration, LedType, PortCalibration, WhiteBalance,
+};
+pub use capture::{Capture, CaptureBus, CaptureDirection, CaptureTransport, CapturedFrame};
+pub use command::*;
+pub use connection::{FastConnection, FastConnectionLost, FastConnectionRestored, FastError};
+pub use controller::{FastController, RetroPlatform};
+pub use drivers::{
+    Driver, DriverAction, DriverCommand, DriverDefinition, DriverSettings, FastDrivers,
+};
 pub use exp_led_port::*;
 pub use expansion_board::ExpansionBoard;
 #[allow(unused_imports)]
-pub use io_board::IoBoard;
+pub use io_board::{IoAddress, IoBoard, IoLoopChecked, IoNetLoop, IoSwitch, LoopMismatch};
+pub use machine::{LedPort, MachineDescription};
 pub use neutron::Neutron;
-pub use parser::FastIoEvent;
+pub use parser::{ExpBusEvent, FastIoEvent, NodeInfo};
+pub use replay::{Replay, ReplayPort};
+pub use rules::{
+    HardwareRule, HardwareRuleControl, HardwareRuleSet, HardwareRules, HardwareRulesEnabled,
+    RuleKind,
+};
+pub use servo::{ExpansionServos, Servo, ServoPositionApplicator};
+pub use simulator::{SimulatedNeutron, SimulatedPort};
+pub use stepper::{
+    ExpansionSteppers, Stepper, StepperHomingFailed, StepperMoveComplete, StepperState,
+    StepperStatus,
+};
+pub use switches::{
+    FastSwitchSystems, FastSwitches, SwitchDefinition, SwitchRegistry, SwitchStatesInitialized,
+};
+pub use transport::{NoBus, Transport};
+pub use watchdog::{Watchdog, WatchdogConfig, WatchdogExpired};
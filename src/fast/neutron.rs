@@ -1,65 +1,605 @@
 use bevy::prelude::*;
 
 use super::{
-    parser::FastIoEvent,
+    capture::{Capture, CaptureBus},
+    command::IoCommand,
+    connection::{FastConnection, FastConnectionLost, FastConnectionRestored, FastError},
+    controller::FastController,
+    io_board::{IoBoard, IoLoopChecked, IoNetLoop},
+    parser::{ExpBusEvent, FastIoEvent, NodeInfo},
+    replay::Replay,
     resources::{ExpPort, IoNetPort},
     serial::*,
+    simulator::SimulatedNeutron,
+    switches::{FastSwitchSystems, SwitchStatesInitialized},
+    transport::{NoBus, Transport},
+    watchdog::Watchdog,
 };
 use std::{
-    sync::{Arc, Mutex},
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, Mutex,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-/// Neutron - Bevy plugin which connects to the Fast Pinball Neutron board
-#[derive(Default, Clone)]
+/// Neutron - Bevy plugin which connects to the Fast Pinball Neutron board, or another FAST
+/// controller given as `controller`
+#[derive(Clone)]
 pub struct Neutron {
-    pub(crate) io_port_path: &'static str,
-    pub(crate) exp_port_path: &'static str,
+    /// The controller to connect to
+    pub controller: FastController,
+    /// Path of the IO/NET port, or empty to auto-detect it
+    pub io_port_path: &'static str,
+    /// Path of the EXP port, or empty to auto-detect it
+    pub exp_port_path: &'static str,
+    /// IO boards on the IO/NET loop, in loop order
+    pub io_boards: Vec<IoBoard>,
+    /// Time without a watchdog refresh before the hardware turns off every driver
+    pub watchdog: Duration,
+    /// Time to wait for each port to open and for the Neutron to answer at startup
+    pub startup_timeout: Duration,
+    /// Connect to an in-memory board instead of the serial ports, e.g. for tests
+    pub simulator: Option<SimulatedNeutron>,
+    /// Record everything sent and received on both ports to this file
+    pub capture: Option<PathBuf>,
+    /// Play back a capture file instead of connecting to the serial ports
+    pub replay: Option<PathBuf>,
 }
 
+impl Default for Neutron {
+    fn default() -> Self {
+        Self {
+            controller: FastController::default(),
+            io_port_path: Default::default(),
+            exp_port_path: Default::default(),
+            io_boards: Default::default(),
+            watchdog: Duration::from_millis(1000),
+            startup_timeout: Duration::from_secs(10),
+            simulator: None,
+            capture: None,
+            replay: None,
+        }
+    }
+}
+
+/// IO/NET and EXP ports once the startup handshake has completed
+type StartupResult = Result<(Box<dyn Transport>, Box<dyn Transport>), FastError>;
+
 impl Plugin for Neutron {
     fn build(&self, app: &mut bevy::app::App) {
+        app.insert_resource(IoNetLoop(self.io_boards.clone()));
+        app.add_event::<FastIoEvent>();
+        app.add_event::<FastConnectionLost>();
+        app.add_event::<FastConnectionRestored>();
+
+        // Start a fresh capture, which each connection then appends to
+        if let Some(path) = &self.capture {
+            match Capture::create(path) {
+                Ok(_) => info!("Capturing FAST traffic to {}", path.display()),
+                Err(e) => error!("Unable to capture to {}: {e}", path.display()),
+            }
+        }
+
+        // Connect in the background so a missing or unresponsive board doesn't hang the app.
+        // IO boards which can't work with the controller fail without connecting.
+        app.insert_resource(NeutronConfig(self.clone()));
+        match self.controller.check_io_boards(&self.io_boards) {
+            Ok(()) => {
+                app.insert_state(FastConnection::Connecting);
+                app.insert_resource(spawn_connection(self.clone()));
+            }
+            Err(e) => {
+                error!("Unable to connect to {}: {e}", self.controller);
+                app.insert_state(FastConnection::Failed(e));
+            }
+        }
+        app.add_systems(
+            PreUpdate,
+            poll_connection.run_if(
+                in_state(FastConnection::Connecting).or(in_state(FastConnection::Reconnecting)),
+            ),
+        );
+        app.add_systems(
+            FixedFirst,
+            monitor_connection
+                .before(io_read)
+                .before(exp_read)
+                .run_if(in_state(FastConnection::Connected))
+                // FixedFirst can run again before the state changes to `Reconnecting`
+                .run_if(not(resource_exists::<PendingConnection>)),
+        );
+
         // IO/NET port
-        let mut io_port = connect(self.io_port_path);
-
-        // Wait for Neutron to boot up
-        loop {
-            let _ = io_port.write("ID:\r".as_bytes());
-            thread::sleep(Duration::from_millis(50));
-            let mut resp = String::new();
-            let _ = io_port.read_to_string(&mut resp);
-            trace!("Identify board response: {resp}");
-            if resp.starts_with("ID:") && resp.trim_end() != "ID:F" {
-                debug!("{}", resp.trim_end());
-                break;
+        app.add_systems(
+            FixedFirst,
+            io_read.run_if(in_state(FastConnection::Connected)),
+        );
+        app.add_plugins(Watchdog {
+            timeout: self.watchdog,
+        });
+
+        // The boards forget their switch settings when they reset. Once they're set, read
+        // every switch so inputs start out matching the playfield.
+        app.add_event::<SwitchStatesInitialized>();
+        app.init_resource::<SwitchStatesPending>();
+        app.add_systems(
+            OnEnter(FastConnection::Connected),
+            (configure_switches, request_switch_states).chain(),
+        );
+        app.add_systems(
+            Update,
+            switch_states_listener
+                .after(FastSwitchSystems)
+                .run_if(in_state(FastConnection::Connected)),
+        );
+
+        // Check the boards on the IO loop match `io_boards`
+        let has_io_loop = self.controller.has_io_loop();
+        let has_io_boards = move |io_loop: Res<IoNetLoop>| has_io_loop && !io_loop.0.is_empty();
+        app.add_event::<IoLoopChecked>();
+        app.init_resource::<NodeDiscovery>();
+        app.add_systems(
+            OnEnter(FastConnection::Connected),
+            query_nodes.run_if(has_io_boards),
+        );
+        app.add_systems(
+            Update,
+            node_info_listener.run_if(in_state(FastConnection::Connected)),
+        );
+
+        // Expansion port
+        app.add_event::<ExpBusEvent>();
+        app.init_resource::<ExpIdentityQueries>();
+        app.add_systems(
+            FixedFirst,
+            exp_read.run_if(in_state(FastConnection::Connected)),
+        );
+        // Queries sent before the connection was lost will never be answered
+        app.add_systems(OnExit(FastConnection::Connected), clear_exp_queries);
+    }
+}
+
+impl Neutron {
+    /// Open both ports and perform the startup handshake, all within `startup_timeout`
+    fn start(&self) -> StartupResult {
+        let deadline = Instant::now() + self.startup_timeout;
+        let (mut io_port, exp_port) = self.open(deadline)?;
+        self.controller.start(&mut io_port, deadline)?;
+        Ok((io_port, exp_port))
+    }
+
+    /// Open the IO/NET and EXP ports, recording them when capturing
+    fn open(&self, deadline: Instant) -> StartupResult {
+        let (io_port, exp_port) = self.open_ports(deadline)?;
+        let Some(path) = &self.capture else {
+            return Ok((io_port, exp_port));
+        };
+        match Capture::append(path) {
+            Ok(capture) => Ok((
+                Box::new(capture.wrap(CaptureBus::IoNet, io_port)),
+                Box::new(capture.wrap(CaptureBus::Exp, exp_port)),
+            )),
+            Err(e) => {
+                error!("Unable to capture to {}: {e}", path.display());
+                Ok((io_port, exp_port))
             }
         }
+    }
+
+    /// Open the serial ports, or the simulator or replay instead when there is one
+    fn open_ports(&self, deadline: Instant) -> StartupResult {
+        if let Some(path) = &self.replay {
+            info!("Replaying {}", path.display());
+            let replay = Replay::open(path).map_err(|e| FastError::PortOpen {
+                path: path.display().to_string(),
+                reason: e.to_string(),
+            })?;
+            return Ok((Box::new(replay.io_port()), Box::new(replay.exp_port())));
+        }
+        if let Some(simulator) = &self.simulator {
+            info!("Using the simulated {}", self.controller);
+            let exp_port: Box<dyn Transport> = match self.controller.has_exp_bus() {
+                true => Box::new(simulator.exp_port()),
+                false => Box::new(NoBus),
+            };
+            return Ok((Box::new(simulator.io_port()), exp_port));
+        }
+
+        let (io_port_path, exp_port_path) = self.port_paths(deadline)?;
+        let Some(exp_port_path) = exp_port_path else {
+            info!("Using {io_port_path} for IO/NET");
+            let io_port = connect(&io_port_path, deadline)?;
+            return Ok((Box::new(io_port), Box::new(NoBus)));
+        };
+        info!("Using {io_port_path} for IO/NET and {exp_port_path} for EXP");
+        let io_port = connect(&io_port_path, deadline)?;
+        let exp_port = connect(&exp_port_path, deadline)?;
+        Ok((Box::new(io_port), Box::new(exp_port)))
+    }
+
+    /// The IO/NET and EXP port paths, auto-detecting any that weren't given. There's no EXP
+    /// port path for a controller without an EXP bus.
+    fn port_paths(&self, deadline: Instant) -> Result<(String, Option<String>), FastError> {
+        let exp = self.controller.has_exp_bus();
+        if !self.io_port_path.is_empty() && (!self.exp_port_path.is_empty() || !exp) {
+            return Ok((
+                self.io_port_path.to_string(),
+                exp.then(|| self.exp_port_path.to_string()),
+            ));
+        }
+
+        // Explicit paths win, and aren't probed as they may belong to something else
+        let skip = [self.io_port_path, self.exp_port_path];
+        let discovered = discover(&skip, deadline, exp);
+        let io_port_path = match self.io_port_path {
+            "" => discovered.io_port_path.ok_or(FastError::NotFound {
+                bus: "IO/NET".to_string(),
+            })?,
+            path => path.to_string(),
+        };
+        if !exp {
+            return Ok((io_port_path, None));
+        }
+        let exp_port_path = match self.exp_port_path {
+            "" => discovered.exp_port_path.ok_or(FastError::NotFound {
+                bus: "EXP".to_string(),
+            })?,
+            path => path.to_string(),
+        };
+        Ok((io_port_path, Some(exp_port_path)))
+    }
+}
+
+/// The plugin settings, kept for reconnecting
+#[derive(Resource)]
+struct NeutronConfig(Neutron);
+
+/// Result of the startup handshake, sent from the connecting thread
+#[derive(Resource)]
+struct PendingConnection(Mutex<Receiver<StartupResult>>);
+
+fn configure_switches(io_loop: Res<IoNetLoop>, port: ResMut<IoNetPort>) {
+    for config in io_loop.switch_configs() {
+        io_write(IoCommand::SwitchConfig(config).to_string(), &port);
+    }
+}
+
+/// Whether the `SA:` sent after connecting is still waiting for its reply
+#[derive(Resource, Debug, Default)]
+struct SwitchStatesPending(bool);
+
+fn request_switch_states(mut pending: ResMut<SwitchStatesPending>, port: ResMut<IoNetPort>) {
+    pending.0 = true;
+    io_write(IoCommand::SwitchStates.to_string(), &port);
+}
+
+/// Only the first `SA:` reply after connecting initializes the switches, not any later
+/// dump
+fn switch_states_listener(
+    mut ev_io: EventReader<FastIoEvent>,
+    mut pending: ResMut<SwitchStatesPending>,
+    mut ev: EventWriter<SwitchStatesInitialized>,
+) {
+    for event in ev_io.read() {
+        if matches!(event, FastIoEvent::SwitchStates(_)) && pending.0 {
+            pending.0 = false;
+            ev.write(SwitchStatesInitialized);
+        }
+    }
+}
 
-        // Tell neutron which board it is
-        {
-            let _ = io_port.write("CH:2000,0\r".as_bytes());
-            thread::sleep(Duration::from_millis(50));
-            let mut resp = String::new();
-            let _ = io_port.read_to_string(&mut resp);
-            trace!("Configured Hardware response: {resp}");
-            if resp.trim_end() != "CH:P" {
-                error!("{}", resp.trim_end());
-                panic!("Attempted to configure hardware as Neutron but failed");
+/// Replies to the `NN:` queries sent after connecting
+#[derive(Resource, Debug, Default)]
+struct NodeDiscovery {
+    queried: usize,
+    replies: usize,
+    nodes: Vec<NodeInfo>,
+    /// `Time<Real>` elapsed time to stop waiting for replies, while still waiting
+    deadline: Option<Duration>,
+}
+
+/// Time to wait for every IO board to answer `NN:` before checking those which did
+const NODE_QUERY_TIMEOUT: Duration = Duration::from_secs(1);
+
+fn query_nodes(
+    io_loop: Res<IoNetLoop>,
+    mut discovery: ResMut<NodeDiscovery>,
+    time: Res<Time<Real>>,
+    port: ResMut<IoNetPort>,
+) {
+    // One more than configured, to find a board which shouldn't be there
+    let queried = io_loop.0.len() + 1;
+    *discovery = NodeDiscovery {
+        queried,
+        deadline: Some(time.elapsed() + NODE_QUERY_TIMEOUT),
+        ..Default::default()
+    };
+    for node in 0..queried {
+        io_write(IoCommand::NodeInfo { node: node as u8 }.to_string(), &port);
+    }
+}
+
+/// `NN:F` replies don't say which node they're for, so wait for every reply before
+/// comparing the loop with the configuration. A loop which is broken may not answer at
+/// all, so whatever arrived is compared once `NODE_QUERY_TIMEOUT` passes.
+fn node_info_listener(
+    io_loop: Res<IoNetLoop>,
+    mut discovery: ResMut<NodeDiscovery>,
+    time: Res<Time<Real>>,
+    mut ev_io: EventReader<FastIoEvent>,
+    mut ev: EventWriter<IoLoopChecked>,
+) {
+    let Some(deadline) = discovery.deadline else {
+        ev_io.clear();
+        return;
+    };
+    for event in ev_io.read() {
+        match event {
+            FastIoEvent::NodeInfo(info) => discovery.nodes.push(info.clone()),
+            FastIoEvent::Failed { command } if command == "NN" => {}
+            _ => continue,
+        }
+        discovery.replies += 1;
+    }
+    if discovery.replies < discovery.queried && time.elapsed() < deadline {
+        return;
+    }
+    discovery.deadline = None;
+
+    let mismatches = io_loop.verify(&discovery.nodes);
+    for mismatch in &mismatches {
+        error!("{mismatch}");
+    }
+    if mismatches.is_empty() {
+        info!("Found the {} configured IO boards", io_loop.0.len());
+    }
+    ev.write(IoLoopChecked {
+        nodes: discovery.nodes.clone(),
+        mismatches,
+    });
+}
+
+fn clear_exp_queries(mut queries: ResMut<ExpIdentityQueries>) {
+    queries.0.clear();
+}
+
+fn spawn_connection(neutron: Neutron) -> PendingConnection {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(neutron.start());
+    });
+    PendingConnection(Mutex::new(receiver))
+}
+
+fn poll_connection(
+    pending: Res<PendingConnection>,
+    config: Res<NeutronConfig>,
+    state: Res<State<FastConnection>>,
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<FastConnection>>,
+    mut ev_restored: EventWriter<FastConnectionRestored>,
+) {
+    let result = match pending.0.lock().unwrap().try_recv() {
+        Ok(result) => result,
+        Err(TryRecvError::Empty) => return,
+        Err(TryRecvError::Disconnected) => Err(FastError::Aborted),
+    };
+    let reconnecting = *state.get() == FastConnection::Reconnecting;
+
+    match result {
+        Ok((io_port, exp_port)) => {
+            info!("Connected to {}", config.0.controller);
+            commands.insert_resource(IoNetPort(Arc::new(Mutex::new(io_port))));
+            commands.insert_resource(ExpPort(Arc::new(Mutex::new(exp_port))));
+            next_state.set(FastConnection::Connected);
+            if reconnecting {
+                ev_restored.write(FastConnectionRestored);
             }
         }
+        Err(e) if reconnecting => {
+            // Keep trying for as long as it takes for the board to come back
+            warn!("Unable to reconnect to {}: {e}", config.0.controller);
+            commands.insert_resource(spawn_connection(config.0.clone()));
+            return;
+        }
+        Err(e) => {
+            error!("Unable to connect to {}: {e}", config.0.controller);
+            next_state.set(FastConnection::Failed(e));
+        }
+    }
+    commands.remove_resource::<PendingConnection>();
+}
+
+/// Watch for either port going away, e.g. a USB hiccup, and re-open them in the background
+fn monitor_connection(
+    io_port: Res<IoNetPort>,
+    exp_port: Res<ExpPort>,
+    config: Res<NeutronConfig>,
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<FastConnection>>,
+    mut ev_lost: EventWriter<FastConnectionLost>,
+) {
+    let io_lost = !io_port.0.lock().unwrap().is_connected();
+    let exp_lost = !exp_port.0.lock().unwrap().is_connected();
+    if io_lost || exp_lost {
+        error!("Lost connection to {}, reconnecting", config.0.controller);
+        ev_lost.write(FastConnectionLost);
+        next_state.set(FastConnection::Reconnecting);
+        commands.insert_resource(spawn_connection(config.0.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fast::{controller::RetroPlatform, io_board::LoopMismatch, mock_port::MockPort};
+    use bevy::{state::app::StatesPlugin, time::TimeUpdateStrategy};
+
+    fn app_in_state(state: FastConnection) -> App {
+        let mut app = App::new();
+        app.add_plugins(StatesPlugin);
+        app.insert_state(state);
+        app.add_event::<FastConnectionLost>();
+        app.add_event::<FastConnectionRestored>();
+        app.insert_resource(NeutronConfig(Neutron {
+            startup_timeout: Duration::ZERO,
+            ..Default::default()
+        }));
+        app
+    }
+
+    #[test]
+    fn it_reconnects_when_a_port_is_lost() {
+        let mut app = app_in_state(FastConnection::Connected);
+        let io_port = MockPort::default();
+        io_port.disconnect();
+        app.insert_resource(IoNetPort(Arc::new(Mutex::new(Box::new(io_port)))));
+        app.insert_resource(ExpPort(Arc::new(Mutex::new(Box::new(MockPort::default())))));
+        // Long enough for several fixed timesteps in the frame the port is lost
+        app.add_plugins(MinimalPlugins);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            100,
+        )));
+        app.add_systems(
+            FixedFirst,
+            monitor_connection
+                .run_if(in_state(FastConnection::Connected))
+                .run_if(not(resource_exists::<PendingConnection>)),
+        );
+        for _ in 0..3 {
+            app.update();
+        }
+
+        let state = app.world().resource::<State<FastConnection>>();
+        assert_eq!(*state.get(), FastConnection::Reconnecting);
+        let events = app.world().resource::<Events<FastConnectionLost>>();
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn it_restores_the_connection() {
+        let mut app = app_in_state(FastConnection::Reconnecting);
+        let (sender, receiver) = mpsc::channel();
+        app.insert_resource(PendingConnection(Mutex::new(receiver)));
+        app.add_systems(
+            Update,
+            poll_connection.run_if(in_state(FastConnection::Reconnecting)),
+        );
 
-        // TODO: watchdog
+        let io_port: Box<dyn Transport> = Box::new(MockPort::default());
+        let exp_port: Box<dyn Transport> = Box::new(MockPort::default());
+        sender.send(Ok((io_port, exp_port))).unwrap();
+        app.update();
+        app.update();
 
-        let mutex = Mutex::new(io_port);
-        app.insert_resource(IoNetPort(Arc::new(mutex)));
+        let state = app.world().resource::<State<FastConnection>>();
+        assert_eq!(*state.get(), FastConnection::Connected);
+        assert!(app.world().contains_resource::<IoNetPort>());
+        let events = app.world().resource::<Events<FastConnectionRestored>>();
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn it_checks_the_io_loop_when_a_board_never_answers() {
+        let mut app = app_in_state(FastConnection::Connected);
+        app.add_plugins(MinimalPlugins);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            250,
+        )));
+        let io_port = MockPort::default();
+        app.insert_resource(IoNetPort(Arc::new(Mutex::new(Box::new(io_port.clone())))));
+        let (switches, coils) = (vec![], vec![]);
+        app.insert_resource(IoNetLoop(vec![
+            IoBoard::CabinetIO {
+                switches: switches.clone(),
+                coils: coils.clone(),
+            },
+            IoBoard::Fast3208 { switches, coils },
+        ]));
         app.add_event::<FastIoEvent>();
-        app.add_systems(FixedFirst, io_read);
+        app.add_event::<IoLoopChecked>();
+        app.init_resource::<NodeDiscovery>();
+        app.add_systems(OnEnter(FastConnection::Connected), query_nodes);
+        app.add_systems(Update, node_info_listener);
+        app.update();
+        assert_eq!(io_port.written(), vec!["NN:00", "NN:01", "NN:02"]);
 
-        // Expansion port
-        let exp_path = connect(self.exp_port_path);
-        let mutex = Mutex::new(exp_path);
-        app.insert_resource(ExpPort(Arc::new(mutex)));
-        app.add_systems(FixedFirst, exp_read);
+        // Only the cabinet board answers
+        let cabinet = NodeInfo {
+            node: 0,
+            model: "FP-I/O-0024-1".to_string(),
+            firmware: "01.10".to_string(),
+            drivers: 8,
+            switches: 24,
+        };
+        app.world_mut()
+            .send_event(FastIoEvent::NodeInfo(cabinet.clone()));
+        let mut cursor = app.world().resource::<Events<IoLoopChecked>>().get_cursor();
+        let mut checked = vec![];
+        for _ in 0..6 {
+            app.update();
+            let events = app.world().resource::<Events<IoLoopChecked>>();
+            checked.extend(cursor.read(events).cloned());
+        }
+        assert_eq!(
+            checked,
+            vec![IoLoopChecked {
+                nodes: vec![cabinet],
+                mismatches: vec![LoopMismatch::Missing {
+                    board: 1,
+                    expected: "FP-I/O-3208"
+                }],
+            }]
+        );
+    }
+
+    #[test]
+    fn it_initializes_the_switches_from_the_first_dump() {
+        let mut app = app_in_state(FastConnection::Connected);
+        let io_port = MockPort::default();
+        app.insert_resource(IoNetPort(Arc::new(Mutex::new(Box::new(io_port)))));
+        app.add_event::<FastIoEvent>();
+        app.add_event::<SwitchStatesInitialized>();
+        app.init_resource::<SwitchStatesPending>();
+        app.add_systems(OnEnter(FastConnection::Connected), request_switch_states);
+        app.add_systems(Update, switch_states_listener);
+
+        let mut cursor = app
+            .world()
+            .resource::<Events<SwitchStatesInitialized>>()
+            .get_cursor();
+        for _ in 0..2 {
+            app.world_mut()
+                .send_event(FastIoEvent::SwitchStates(vec![false; 8]));
+            app.update();
+        }
+        let events = app.world().resource::<Events<SwitchStatesInitialized>>();
+        assert_eq!(cursor.read(events).count(), 1);
+    }
+
+    #[test]
+    fn it_fails_without_connecting_when_the_io_boards_dont_suit_the_controller() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin));
+        app.add_plugins(Neutron {
+            io_boards: vec![IoBoard::Retro {
+                platform: RetroPlatform::Wpc95,
+                switches: vec![],
+                coils: vec![],
+            }],
+            simulator: Some(SimulatedNeutron::new(0)),
+            ..Default::default()
+        });
+        app.update();
+
+        let state = app.world().resource::<State<FastConnection>>();
+        assert!(matches!(
+            state.get(),
+            FastConnection::Failed(FastError::InvalidIoBoards { .. })
+        ));
+        assert!(!app.world().contains_resource::<PendingConnection>());
     }
 }
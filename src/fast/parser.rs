@@ -1,9 +1,68 @@
 use bevy::prelude::*;
+use std::{collections::VecDeque, fmt, time::Duration};
 
+/// Incoming data from the FAST IO/NET port
 #[derive(Event, Debug, Clone, PartialEq, Eq)]
 pub enum FastIoEvent {
-    SwitchOpened { id: String },
-    SwitchClosed { id: String },
+    /// `/L:` - A switch opened. `time` is the `Time<Real>` elapsed time it was read at,
+    /// stamped by `io_read`.
+    SwitchOpened {
+        id: u16,
+        time: Duration,
+    },
+    /// `-L:` - A switch closed, stamped like `SwitchOpened`
+    SwitchClosed {
+        id: u16,
+        time: Duration,
+    },
+    /// `ID:` - Identity of the board, e.g. `ID:NET FP-CPU-2000 02.13`
+    Identity {
+        processor: String,
+        product: String,
+        firmware: String,
+    },
+    /// `SA:` - State of every switch, `true` when closed
+    SwitchStates(Vec<bool>),
+    /// `NN:` - Information about a node on the IO loop
+    NodeInfo(NodeInfo),
+    /// `WD:` - Time remaining before the watchdog expires
+    WatchdogRemaining(Duration),
+    /// `XX:P` - The command was accepted
+    Passed {
+        command: String,
+    },
+    /// `XX:F` - The command failed
+    Failed {
+        command: String,
+    },
+}
+
+/// Incoming data from the FAST EXP port
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub enum ExpBusEvent {
+    /// `ID:` - The board asked with `ID@<address>:`, e.g. `ID:EXP FP-EXP-0091 0.10`
+    Identity {
+        address: String,
+        processor: String,
+        product: String,
+        firmware: String,
+    },
+    /// `ID:F` - Nothing answered `ID@<address>:`, or no reply came within
+    /// `EXP_IDENTITY_TIMEOUT`
+    BoardMissing { address: String },
+    /// `XX:P` - The command was accepted
+    Passed { command: String },
+    /// `XX:F` - The command failed, e.g. `RS:F` for LEDs on an address with no board
+    Failed { command: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeInfo {
+    pub node: u8,
+    pub model: String,
+    pub firmware: String,
+    pub drivers: u8,
+    pub switches: u8,
 }
 
 /// Convert FAST pinball response string into a Message
@@ -11,16 +70,131 @@ pub fn parse(input: String) -> Result<FastIoEvent, String> {
     match input.split_once(":") {
         None => Err("Invalid message syntax: Missing command.".to_string()),
         Some((cmd, all_args)) => {
-            let args = parse_args(all_args.trim_end_matches("\r"));
-            match cmd {
-                "-L" => Ok(FastIoEvent::SwitchClosed {
-                    id: args[0].to_string(),
+            let all_args = all_args.trim_end_matches("\r");
+            let args = parse_args(all_args);
+            let byte = |arg: &str| {
+                parse_hex(arg)
+                    .and_then(|value| u8::try_from(value).ok())
+                    .ok_or(input.clone())
+            };
+            let word = |arg: &str| {
+                parse_hex(arg)
+                    .and_then(|value| u16::try_from(value).ok())
+                    .ok_or(input.clone())
+            };
+            match (cmd, args.as_slice()) {
+                (cmd, ["P"]) => Ok(FastIoEvent::Passed {
+                    command: cmd.to_string(),
                 }),
-                "/L" => Ok(FastIoEvent::SwitchOpened {
-                    id: args[0].to_string(),
+                (cmd, ["F"]) => Ok(FastIoEvent::Failed {
+                    command: cmd.to_string(),
                 }),
-                raw => Err(raw.to_string()),
+                ("-L", [id]) => Ok(FastIoEvent::SwitchClosed {
+                    id: word(id)?,
+                    time: Duration::ZERO,
+                }),
+                ("/L", [id]) => Ok(FastIoEvent::SwitchOpened {
+                    id: word(id)?,
+                    time: Duration::ZERO,
+                }),
+                ("ID", _) => {
+                    let mut parts = all_args.split_whitespace();
+                    match (parts.next(), parts.next(), parts.next()) {
+                        (Some(processor), Some(product), Some(firmware)) => {
+                            Ok(FastIoEvent::Identity {
+                                processor: processor.to_string(),
+                                product: product.to_string(),
+                                firmware: firmware.to_string(),
+                            })
+                        }
+                        _ => Err(input),
+                    }
+                }
+                ("SA", [count, states]) => {
+                    let count = parse_hex(count).ok_or(input.clone())? as usize;
+                    let states = parse_switch_states(count, states).ok_or(input)?;
+                    Ok(FastIoEvent::SwitchStates(states))
+                }
+                ("NN", [node, model, firmware, drivers, switches, ..]) => {
+                    Ok(FastIoEvent::NodeInfo(NodeInfo {
+                        node: byte(node)?,
+                        model: model.trim().to_string(),
+                        firmware: firmware.trim().to_string(),
+                        drivers: byte(drivers)?,
+                        switches: byte(switches)?,
+                    }))
+                }
+                ("WD", [remaining]) => {
+                    let ms = parse_hex(remaining).ok_or(input.clone())?;
+                    Ok(FastIoEvent::WatchdogRemaining(Duration::from_millis(
+                        ms as u64,
+                    )))
+                }
+                (raw, _) => Err(raw.to_string()),
+            }
+        }
+    }
+}
+
+/// Convert a FAST EXP response into an event. Replies don't say which board sent them, so
+/// identities and `ID:F` failures are paired with the `ID@` queries in `queries`, oldest
+/// first. Each query is an address and the deadline for its reply.
+pub fn parse_exp(
+    input: String,
+    queries: &mut VecDeque<(&'static str, Duration)>,
+) -> Result<ExpBusEvent, String> {
+    match parse(input.clone())? {
+        FastIoEvent::Identity {
+            processor,
+            product,
+            firmware,
+        } => Ok(ExpBusEvent::Identity {
+            address: queries.pop_front().ok_or(input)?.0.to_string(),
+            processor,
+            product,
+            firmware,
+        }),
+        FastIoEvent::Failed { command } if command == "ID" => match queries.pop_front() {
+            Some((address, _)) => Ok(ExpBusEvent::BoardMissing {
+                address: address.to_string(),
+            }),
+            None => Ok(ExpBusEvent::Failed { command }),
+        },
+        FastIoEvent::Failed { command } => Ok(ExpBusEvent::Failed { command }),
+        FastIoEvent::Passed { command } => Ok(ExpBusEvent::Passed { command }),
+        _ => Err(input),
+    }
+}
+
+impl fmt::Display for FastIoEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::SwitchOpened { id, .. } => write!(f, "/L:{:02X}", id),
+            Self::SwitchClosed { id, .. } => write!(f, "-L:{:02X}", id),
+            Self::Identity {
+                processor,
+                product,
+                firmware,
+            } => write!(f, "ID:{processor} {product} {firmware}"),
+            Self::SwitchStates(states) => {
+                write!(f, "SA:{:02X},", states.len())?;
+                for chunk in states.chunks(8) {
+                    let byte = chunk
+                        .iter()
+                        .enumerate()
+                        .fold(0u8, |byte, (bit, closed)| byte | ((*closed as u8) << bit));
+                    write!(f, "{:02X}", byte)?;
+                }
+                Ok(())
             }
+            Self::NodeInfo(info) => write!(
+                f,
+                "NN:{:02X},{},{},{:02X},{:02X}",
+                info.node, info.model, info.firmware, info.drivers, info.switches
+            ),
+            Self::WatchdogRemaining(remaining) => write!(f, "WD:{:X}", remaining.as_millis()),
+            Self::Passed { command } => write!(f, "{command}:P"),
+            Self::Failed { command } => write!(f, "{command}:F"),
         }
     }
 }
@@ -28,9 +202,191 @@ pub fn parse(input: String) -> Result<FastIoEvent, String> {
 /// Convert everything after the ":" into a list of arguments
 /// Returns [] when there are no arguments
 fn parse_args(all_args: &str) -> Vec<&str> {
-    if all_args.len() > 0 {
+    if !all_args.is_empty() {
         all_args.split(",").collect()
     } else {
         vec![]
     }
 }
+
+fn parse_hex(arg: &str) -> Option<u32> {
+    u32::from_str_radix(arg.trim(), 16).ok()
+}
+
+/// Switch states are sent as hex bytes, with the lowest switch number in the lowest bit
+fn parse_switch_states(count: usize, states: &str) -> Option<Vec<bool>> {
+    if !states.len().is_multiple_of(2) || !states.is_ascii() {
+        return None;
+    }
+    let bytes = (0..states.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&states[i..i + 2], 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+    let switches = bytes
+        .iter()
+        .flat_map(|byte| (0..8).map(move |bit| byte & (1 << bit) != 0))
+        .take(count)
+        .collect::<Vec<_>>();
+    (switches.len() == count).then_some(switches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trip(event: FastIoEvent) {
+        let serialized = event.to_string();
+        assert_eq!(parse(serialized.clone()), Ok(event), "{serialized}");
+    }
+
+    #[test]
+    fn it_parses_switch_reports() {
+        assert_eq!(
+            parse("-L:1A\r".to_string()),
+            Ok(FastIoEvent::SwitchClosed {
+                id: 0x1a,
+                time: Duration::ZERO,
+            })
+        );
+        assert_eq!(
+            parse("/L:03".to_string()),
+            Ok(FastIoEvent::SwitchOpened {
+                id: 0x03,
+                time: Duration::ZERO,
+            })
+        );
+    }
+
+    #[test]
+    fn it_parses_identity() {
+        assert_eq!(
+            parse("ID:NET FP-CPU-2000 02.13".to_string()),
+            Ok(FastIoEvent::Identity {
+                processor: "NET".to_string(),
+                product: "FP-CPU-2000".to_string(),
+                firmware: "02.13".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn it_parses_acks_and_failures() {
+        assert_eq!(
+            parse("CH:P".to_string()),
+            Ok(FastIoEvent::Passed {
+                command: "CH".to_string()
+            })
+        );
+        assert_eq!(
+            parse("ID:F".to_string()),
+            Ok(FastIoEvent::Failed {
+                command: "ID".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn it_parses_switch_states() {
+        let event = parse("SA:0A,0502".to_string());
+        let mut states = vec![false; 10];
+        states[0] = true;
+        states[2] = true;
+        states[9] = true;
+        assert_eq!(event, Ok(FastIoEvent::SwitchStates(states)));
+    }
+
+    #[test]
+    fn it_round_trips_responses() {
+        assert_round_trip(FastIoEvent::SwitchClosed {
+            id: 0x4f,
+            time: Duration::ZERO,
+        });
+        assert_round_trip(FastIoEvent::SwitchOpened {
+            id: 0x104,
+            time: Duration::ZERO,
+        });
+        assert_round_trip(FastIoEvent::Identity {
+            processor: "NET".to_string(),
+            product: "FP-CPU-2000".to_string(),
+            firmware: "02.13".to_string(),
+        });
+        assert_round_trip(FastIoEvent::SwitchStates(vec![
+            true, false, false, true, false, false, false, false, true, true, false,
+        ]));
+        assert_round_trip(FastIoEvent::NodeInfo(NodeInfo {
+            node: 1,
+            model: "FP-I/O-3208-2".to_string(),
+            firmware: "01.05".to_string(),
+            drivers: 8,
+            switches: 32,
+        }));
+        assert_round_trip(FastIoEvent::WatchdogRemaining(Duration::from_millis(900)));
+        assert_round_trip(FastIoEvent::Passed {
+            command: "DL".to_string(),
+        });
+        assert_round_trip(FastIoEvent::Failed {
+            command: "SL".to_string(),
+        });
+    }
+
+    #[test]
+    fn it_pairs_exp_replies_with_queries() {
+        let mut queries = VecDeque::from([("48", Duration::ZERO), ("88", Duration::ZERO)]);
+        assert_eq!(
+            parse_exp("ID:EXP FP-CPU-2000 02.13".to_string(), &mut queries),
+            Ok(ExpBusEvent::Identity {
+                address: "48".to_string(),
+                processor: "EXP".to_string(),
+                product: "FP-CPU-2000".to_string(),
+                firmware: "02.13".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_exp("ID:F".to_string(), &mut queries),
+            Ok(ExpBusEvent::BoardMissing {
+                address: "88".to_string()
+            })
+        );
+        // Only `ID:F` answers a query
+        queries.push_back(("B4", Duration::ZERO));
+        assert_eq!(
+            parse_exp("XX:F".to_string(), &mut queries),
+            Ok(ExpBusEvent::Failed {
+                command: "XX".to_string()
+            })
+        );
+        assert_eq!(queries.len(), 1);
+        queries.clear();
+        assert_eq!(
+            parse_exp("RS:F".to_string(), &mut queries),
+            Ok(ExpBusEvent::Failed {
+                command: "RS".to_string()
+            })
+        );
+        assert!(parse_exp("ID:EXP FP-EXP-0071 0.10".to_string(), &mut queries).is_err());
+        assert!(parse_exp("-L:01".to_string(), &mut queries).is_err());
+    }
+
+    #[test]
+    fn it_rejects_unknown_messages() {
+        assert!(parse("ZZ:01".to_string()).is_err());
+        assert!(parse("-L:XY".to_string()).is_err());
+        assert!(parse("SA:10,01".to_string()).is_err());
+    }
+
+    #[test]
+    fn it_rejects_values_too_large_for_their_field() {
+        assert!(parse("-L:1FFFF".to_string()).is_err());
+        assert!(parse("/L:10000".to_string()).is_err());
+        assert!(parse("NN:100,FP-I/O-3208-2,01.05,08,20".to_string()).is_err());
+        assert!(parse("NN:01,FP-I/O-3208-2,01.05,108,20".to_string()).is_err());
+        assert!(parse("NN:01,FP-I/O-3208-2,01.05,08,120".to_string()).is_err());
+        assert_eq!(
+            parse("-L:FFFF".to_string()),
+            Ok(FastIoEvent::SwitchClosed {
+                id: 0xffff,
+                time: Duration::ZERO,
+            })
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use bevy::prelude::*;
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::{
+    capture::{CaptureBus, CaptureDirection, CapturedFrame},
+    transport::Transport,
+};
+
+/// Replay - Plays a capture back into the plugins at its original timing
+///
+/// Everything the hardware sent in the capture is read back from the matching port once
+/// as much time has passed since the replay started as had passed since the capture
+/// started. Anything the game writes is discarded.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    /// Frames still to be read, by bus
+    pending: Arc<Mutex<Vec<(CaptureBus, Duration, String)>>>,
+    started: Instant,
+}
+
+impl Replay {
+    /// Load a capture file, starting the clock now
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let frames = fs::read_to_string(path)?
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| line.parse::<CapturedFrame>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|line| io::Error::new(io::ErrorKind::InvalidData, line))?;
+        Ok(Self::from_frames(frames, Instant::now()))
+    }
+
+    pub fn from_frames(frames: Vec<CapturedFrame>, started: Instant) -> Self {
+        let first = frames.first().map(|frame| frame.time).unwrap_or_default();
+        let pending = frames
+            .into_iter()
+            .filter(|frame| frame.direction == CaptureDirection::Received)
+            .map(|frame| (frame.bus, frame.time.saturating_sub(first), frame.message))
+            .collect();
+        Self {
+            pending: Arc::new(Mutex::new(pending)),
+            started,
+        }
+    }
+
+    pub fn io_port(&self) -> ReplayPort {
+        self.port(CaptureBus::IoNet)
+    }
+
+    pub fn exp_port(&self) -> ReplayPort {
+        self.port(CaptureBus::Exp)
+    }
+
+    fn port(&self, bus: CaptureBus) -> ReplayPort {
+        ReplayPort {
+            replay: self.clone(),
+            bus,
+            unread: VecDeque::new(),
+        }
+    }
+
+    /// Removes the frames for a bus which are due by now
+    fn take_due(&self, bus: CaptureBus) -> Vec<String> {
+        let elapsed = self.started.elapsed();
+        let mut due = vec![];
+        self.pending
+            .lock()
+            .unwrap()
+            .retain(|(frame_bus, time, message)| {
+                let is_due = *frame_bus == bus && *time <= elapsed;
+                if is_due {
+                    due.push(message.clone());
+                }
+                !is_due
+            });
+        due
+    }
+}
+
+/// One port of a `Replay`
+#[derive(Debug)]
+pub struct ReplayPort {
+    replay: Replay,
+    bus: CaptureBus,
+    unread: VecDeque<u8>,
+}
+
+impl Read for ReplayPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        for message in self.replay.take_due(self.bus) {
+            self.unread.extend(format!("{message}\r").as_bytes());
+        }
+        if self.unread.is_empty() {
+            // Like a real port, reading with nothing available times out
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        let count = buf.len().min(self.unread.len());
+        for (i, byte) in self.unread.drain(..count).enumerate() {
+            buf[i] = byte;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for ReplayPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        trace!("Replay discarded {}", String::from_utf8_lossy(buf));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for ReplayPort {
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(
+        ms: u64,
+        bus: CaptureBus,
+        direction: CaptureDirection,
+        message: &str,
+    ) -> CapturedFrame {
+        CapturedFrame {
+            time: Duration::from_millis(ms),
+            bus,
+            direction,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn it_replays_received_frames_at_their_original_time() {
+        let frames = vec![
+            frame(1000, CaptureBus::IoNet, CaptureDirection::Sent, "ID:"),
+            frame(
+                1001,
+                CaptureBus::IoNet,
+                CaptureDirection::Received,
+                "ID:NET FP-CPU-2000 02.13",
+            ),
+            frame(1002, CaptureBus::Exp, CaptureDirection::Received, "XX:F"),
+            frame(
+                61_000,
+                CaptureBus::IoNet,
+                CaptureDirection::Received,
+                "-L:02",
+            ),
+        ];
+        let replay = Replay::from_frames(frames, Instant::now() - Duration::from_secs(1));
+        let mut port = replay.io_port();
+
+        let mut read = String::new();
+        let _ = port.read_to_string(&mut read);
+        assert_eq!(read, "ID:NET FP-CPU-2000 02.13\r");
+        assert_eq!(replay.pending.lock().unwrap().len(), 2);
+    }
+}
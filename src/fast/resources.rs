@@ -1,9 +1,10 @@
 use bevy::prelude::*;
-use serialport::SerialPort;
 use std::sync::{Arc, Mutex};
 
+use super::transport::Transport;
+
 #[derive(Resource, Debug)]
-pub struct IoNetPort(pub Arc<Mutex<Box<dyn SerialPort>>>);
+pub struct IoNetPort(pub Arc<Mutex<Box<dyn Transport>>>);
 
 #[derive(Resource, Debug)]
-pub struct ExpPort(pub Arc<Mutex<Box<dyn SerialPort>>>);
+pub struct ExpPort(pub Arc<Mutex<Box<dyn Transport>>>);
@@ -0,0 +1,312 @@
+use bevy::prelude::*;
+
+use crate::pinball::{MachineState, Tilt};
+
+use super::{
+    command::{DriverConfig, DriverMode, DriverTrigger, IoCommand},
+    connection::FastConnection,
+    io_board::{IoAddress, IoNetLoop},
+    resources::IoNetPort,
+    serial::io_write,
+};
+
+/// HardwareRules - Ties switches to drivers on the FAST hardware
+///
+/// Rules run on the IO boards themselves, so flippers, slings and pop bumpers respond
+/// without a round trip through the Bevy schedule. All rules are enabled together when
+/// the game starts, and disabled together when the game ends or the machine is tilted.
+/// They can also be switched manually with a `HardwareRuleControl` event, and the current
+/// state is sent again whenever the connection is restored. The `Neutron`
+/// plugin must be added first as it provides the IO board layout.
+pub struct HardwareRules {
+    pub rules: Vec<HardwareRule>,
+}
+
+/// A driver which is fired by a switch
+#[derive(Debug, Clone)]
+pub struct HardwareRule {
+    pub name: &'static str,
+    pub switch: IoAddress,
+    pub driver: IoAddress,
+    pub kind: RuleKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum RuleKind {
+    /// Pulse at full power until the EOS switch closes, then hold while the button is held
+    FlipperEos {
+        eos_switch: IoAddress,
+        pulse_ms: u8,
+        hold_power: u8,
+    },
+    /// Pulse at full power, then hold while the button is held
+    Flipper { pulse_ms: u8, hold_power: u8 },
+    /// Pulse once each time the switch closes, e.g. pop bumpers and slingshots
+    PulseOnClose { pulse_ms: u8, recycle_ms: u8 },
+}
+
+/// Enable or disable every hardware rule
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HardwareRuleControl {
+    Enable,
+    Disable,
+}
+
+/// Driver configuration for every hardware rule, as sent when the rules are enabled
+#[derive(Resource, Debug, Default, Clone)]
+pub struct HardwareRuleSet(pub Vec<DriverConfig>);
+
+/// Whether the hardware rules should currently be enabled
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HardwareRulesEnabled(pub bool);
+
+impl Plugin for HardwareRules {
+    fn build(&self, app: &mut App) {
+        let io_loop = app
+            .world()
+            .get_resource::<IoNetLoop>()
+            .cloned()
+            .unwrap_or_default();
+
+        let configs = self
+            .rules
+            .iter()
+            .filter_map(|rule| match rule_config(rule, &io_loop) {
+                Some(config) => Some(config),
+                None => {
+                    error!(
+                        "Hardware rule {} has an invalid switch or driver",
+                        rule.name
+                    );
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Rules can be added across multiple plugins
+        if let Some(mut rule_set) = app.world_mut().get_resource_mut::<HardwareRuleSet>() {
+            rule_set.0.extend(configs);
+            return;
+        }
+
+        app.insert_resource(HardwareRuleSet(configs));
+        app.init_resource::<HardwareRulesEnabled>();
+        app.add_event::<HardwareRuleControl>();
+        app.add_systems(OnEnter(MachineState::InGame), enable_rules);
+        app.add_systems(OnExit(MachineState::InGame), disable_rules);
+        app.add_systems(Update, (tilt_listener, rule_control_listener).chain());
+
+        // Replay the current state after every (re)connect, as the boards may have reset
+        app.add_systems(OnEnter(FastConnection::Connected), send_rules);
+        app.add_systems(
+            Update,
+            send_rules
+                .after(rule_control_listener)
+                .run_if(in_state(FastConnection::Connected))
+                .run_if(resource_changed::<HardwareRulesEnabled>),
+        );
+    }
+
+    fn is_unique(&self) -> bool {
+        false
+    }
+}
+
+/// Builds the driver configuration for a rule, in its enabled state
+fn rule_config(rule: &HardwareRule, io_loop: &IoNetLoop) -> Option<DriverConfig> {
+    let switch = io_loop.switch_number(rule.switch.board, rule.switch.port)?;
+    let driver = io_loop.driver_number(rule.driver.board, rule.driver.port)?;
+    let mode = match rule.kind {
+        RuleKind::FlipperEos {
+            eos_switch,
+            pulse_ms,
+            hold_power,
+        } => DriverMode::PulseHoldCancel {
+            pulse_ms,
+            pulse_power: 0xff,
+            hold_power,
+            cancel_switch: io_loop.switch_number(eos_switch.board, eos_switch.port)?,
+            recycle_ms: 0,
+        },
+        RuleKind::Flipper {
+            pulse_ms,
+            hold_power,
+        } => DriverMode::PulseHold {
+            pulse_ms,
+            pulse_power: 0xff,
+            hold_power,
+            recycle_ms: 0,
+        },
+        RuleKind::PulseOnClose {
+            pulse_ms,
+            recycle_ms,
+        } => DriverMode::Pulse {
+            pulse_ms,
+            pulse_power: 0xff,
+            second_ms: 0,
+            second_power: 0,
+            recycle_ms,
+        },
+    };
+    Some(DriverConfig {
+        driver,
+        trigger: DriverTrigger {
+            enabled: true,
+            ..Default::default()
+        },
+        switch,
+        mode,
+    })
+}
+
+/// Builds the commands which enable or disable every rule
+fn rule_commands(rule_set: &HardwareRuleSet, enabled: bool) -> Vec<IoCommand> {
+    rule_set
+        .0
+        .iter()
+        .map(|config| {
+            let mut config = *config;
+            config.trigger.enabled = enabled;
+            IoCommand::DriverConfig(config)
+        })
+        .collect()
+}
+
+fn send_rules(
+    rule_set: Res<HardwareRuleSet>,
+    enabled: Res<HardwareRulesEnabled>,
+    port: ResMut<IoNetPort>,
+) {
+    for command in rule_commands(&rule_set, enabled.0) {
+        io_write(command.to_string(), &port);
+    }
+}
+
+fn enable_rules(mut enabled: ResMut<HardwareRulesEnabled>) {
+    enabled.set_if_neq(HardwareRulesEnabled(true));
+}
+
+fn disable_rules(mut enabled: ResMut<HardwareRulesEnabled>) {
+    enabled.set_if_neq(HardwareRulesEnabled(false));
+}
+
+fn tilt_listener(mut ev_tilt: EventReader<Tilt>, mut ev: EventWriter<HardwareRuleControl>) {
+    if ev_tilt.read().count() > 0 {
+        ev.write(HardwareRuleControl::Disable);
+    }
+}
+
+fn rule_control_listener(
+    mut ev: EventReader<HardwareRuleControl>,
+    mut enabled: ResMut<HardwareRulesEnabled>,
+) {
+    // Only the most recent request matters
+    if let Some(control) = ev.read().last() {
+        enabled.set_if_neq(HardwareRulesEnabled(
+            *control == HardwareRuleControl::Enable,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fast::IoBoard;
+
+    fn io_loop() -> IoNetLoop {
+        IoNetLoop(vec![
+            IoBoard::CabinetIO {
+                switches: vec![],
+                coils: vec![],
+            },
+            IoBoard::Fast3208 {
+                switches: vec![],
+                coils: vec![],
+            },
+        ])
+    }
+
+    #[test]
+    fn it_configures_flippers_with_eos() {
+        let rule = HardwareRule {
+            name: "left flipper",
+            switch: IoAddress { board: 0, port: 0 },
+            driver: IoAddress { board: 1, port: 0 },
+            kind: RuleKind::FlipperEos {
+                eos_switch: IoAddress { board: 1, port: 2 },
+                pulse_ms: 30,
+                hold_power: 0x40,
+            },
+        };
+        let config = rule_config(&rule, &io_loop()).unwrap();
+        assert_eq!(
+            IoCommand::DriverConfig(config).to_string(),
+            "DL:08,01,00,75,1E,FF,40,1A,00"
+        );
+    }
+
+    #[test]
+    fn it_enables_and_disables_rules_together() {
+        let rules = [
+            HardwareRule {
+                name: "left sling",
+                switch: IoAddress { board: 1, port: 4 },
+                driver: IoAddress { board: 1, port: 2 },
+                kind: RuleKind::PulseOnClose {
+                    pulse_ms: 12,
+                    recycle_ms: 40,
+                },
+            },
+            HardwareRule {
+                name: "right sling",
+                switch: IoAddress { board: 1, port: 5 },
+                driver: IoAddress { board: 1, port: 3 },
+                kind: RuleKind::PulseOnClose {
+                    pulse_ms: 12,
+                    recycle_ms: 40,
+                },
+            },
+        ];
+        let rule_set = HardwareRuleSet(
+            rules
+                .iter()
+                .map(|rule| rule_config(rule, &io_loop()).unwrap())
+                .collect(),
+        );
+
+        let frames = |enabled| {
+            rule_commands(&rule_set, enabled)
+                .iter()
+                .map(|command| command.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            frames(true),
+            vec![
+                "DL:0A,01,1C,10,0C,FF,00,00,28",
+                "DL:0B,01,1D,10,0C,FF,00,00,28"
+            ]
+        );
+        assert_eq!(
+            frames(false),
+            vec![
+                "DL:0A,00,1C,10,0C,FF,00,00,28",
+                "DL:0B,00,1D,10,0C,FF,00,00,28"
+            ]
+        );
+    }
+
+    #[test]
+    fn it_rejects_rules_for_missing_ports() {
+        let rule = HardwareRule {
+            name: "pop bumper",
+            switch: IoAddress { board: 2, port: 0 },
+            driver: IoAddress { board: 1, port: 0 },
+            kind: RuleKind::PulseOnClose {
+                pulse_ms: 10,
+                recycle_ms: 10,
+            },
+        };
+        assert!(rule_config(&rule, &io_loop()).is_none());
+    }
+}
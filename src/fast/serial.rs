@@ -1,46 +1,128 @@
 use bevy::prelude::*;
+use std::collections::VecDeque;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use serialport::SerialPort;
 
-use crate::fast::parser::parse;
+use crate::fast::parser::{parse, parse_exp};
 
+use super::connection::{identify, FastError};
+use super::framing::LineBuffer;
 use super::resources::{ExpPort, IoNetPort};
-use super::FastIoEvent;
+use super::{ExpBusEvent, FastIoEvent};
 
-pub fn connect(port_path: &str) -> Box<dyn SerialPort> {
+/// Open a FAST serial port, retrying until it opens or the deadline passes
+pub fn connect(port_path: &str, deadline: Instant) -> Result<Box<dyn SerialPort>, FastError> {
     let baud_rate = 921_600;
-    let port = serialport::new(port_path, baud_rate)
-        .parity(serialport::Parity::None)
-        .stop_bits(serialport::StopBits::One)
-        .open();
-    match port {
-        Ok(port) => port,
-        Err(e) => {
-            error!("{:?} - {}", e.kind, e.description);
-            thread::sleep(Duration::from_millis(300));
-            return connect(port_path);
+    loop {
+        let port = serialport::new(port_path, baud_rate)
+            .parity(serialport::Parity::None)
+            .stop_bits(serialport::StopBits::One)
+            .open();
+        match port {
+            Ok(port) => return Ok(port),
+            Err(e) => {
+                error!("{:?} - {}", e.kind, e.description);
+                if Instant::now() >= deadline {
+                    return Err(FastError::PortOpen {
+                        path: port_path.to_string(),
+                        reason: e.description,
+                    });
+                }
+                thread::sleep(Duration::from_millis(300));
+            }
+        }
+    }
+}
+
+/// Paths of the IO/NET and EXP ports, as found by `discover`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DiscoveredPorts {
+    pub io_port_path: Option<String>,
+    pub exp_port_path: Option<String>,
+}
+
+/// Find the FAST ports by asking every serial port to identify itself, retrying until
+/// both are found or the deadline passes. Ports in `skip` are in use and not probed.
+/// Without `exp`, finding the IO/NET port is enough.
+pub fn discover(skip: &[&str], deadline: Instant, exp: bool) -> DiscoveredPorts {
+    loop {
+        let ports = serialport::available_ports().unwrap_or_default();
+        let identities = ports
+            .iter()
+            .filter(|port| !skip.contains(&port.port_name.as_str()))
+            .filter_map(|port| Some((port.port_name.clone(), probe(&port.port_name)?)))
+            .collect::<Vec<_>>();
+        let discovered = pick_ports(&identities);
+        let found =
+            discovered.io_port_path.is_some() && (discovered.exp_port_path.is_some() || !exp);
+        if found || Instant::now() >= deadline {
+            return discovered;
+        }
+        thread::sleep(Duration::from_millis(300));
+    }
+}
+
+/// Ask a single port to identify itself, returning the processor, e.g. `NET` or `EXP`
+fn probe(port_path: &str) -> Option<String> {
+    let mut port = connect(port_path, Instant::now()).ok()?;
+    match identify(&mut port, Instant::now()) {
+        Ok(FastIoEvent::Identity { processor, .. }) => {
+            debug!("Found {processor} on {port_path}");
+            Some(processor)
         }
+        _ => None,
     }
 }
 
-pub fn io_read(port: ResMut<IoNetPort>, mut ev_io: EventWriter<FastIoEvent>) {
+/// Choose the IO/NET and EXP ports from the processor each port identified as
+fn pick_ports(identities: &[(String, String)]) -> DiscoveredPorts {
+    let find = |processor: &str| {
+        identities
+            .iter()
+            .find(|(_, id)| id == processor)
+            .map(|(path, _)| path.clone())
+    };
+    DiscoveredPorts {
+        io_port_path: find("NET"),
+        exp_port_path: find("EXP"),
+    }
+}
+
+pub fn io_read(
+    port: ResMut<IoNetPort>,
+    mut lines: Local<LineBuffer>,
+    time: Res<Time<Real>>,
+    mut ev_io: EventWriter<FastIoEvent>,
+) {
+    // The port is only replaced on (re)connecting, and a partial message from the old one
+    // will never be finished
+    if port.is_changed() {
+        *lines = LineBuffer::default();
+    }
     let mut io_net_port = port.0.lock().unwrap();
     let mut buffer: String = String::new();
     let _ = io_net_port.read_to_string(&mut buffer);
     if buffer.len() > 0 {
         trace!("Read {} bytes from IO/NET: {buffer}", buffer.len());
-        match parse(buffer) {
-            Ok(event) => {
-                ev_io.send(event);
+        for line in lines.push(&buffer) {
+            match parse(line) {
+                Ok(mut event) => {
+                    // Reports carry no time of their own, so they're stamped as they're read
+                    if let FastIoEvent::SwitchOpened { time: read, .. }
+                    | FastIoEvent::SwitchClosed { time: read, .. } = &mut event
+                    {
+                        *read = time.elapsed();
+                    }
+                    ev_io.write(event);
+                }
+                Err(e) => error!("{e}"),
             }
-            Err(e) => error!("{e}"),
         }
     }
 }
 
-#[allow(dead_code)]
 pub fn io_write(data: String, port: &ResMut<IoNetPort>) {
     let mut io_net_port = port.0.lock().unwrap();
     match io_net_port.write(format!("{}\r", data).as_bytes()) {
@@ -49,17 +131,65 @@ pub fn io_write(data: String, port: &ResMut<IoNetPort>) {
     }
 }
 
-pub fn exp_read(port: ResMut<ExpPort>) {
+/// Addresses asked to identify themselves with `ID@<address>:` which haven't answered yet,
+/// oldest first, each with the `Time<Real>` elapsed time by which it should have
+#[derive(Resource, Debug, Default)]
+pub struct ExpIdentityQueries(pub VecDeque<(&'static str, Duration)>);
+
+/// Time to wait for an expansion board to answer `ID@` before reporting it missing
+const EXP_IDENTITY_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub fn exp_read(
+    port: ResMut<ExpPort>,
+    mut lines: Local<LineBuffer>,
+    mut queries: ResMut<ExpIdentityQueries>,
+    time: Res<Time<Real>>,
+    mut ev_exp: EventWriter<ExpBusEvent>,
+) {
+    // As in `io_read`, don't finish a message from the port before a reconnect
+    if port.is_changed() {
+        *lines = LineBuffer::default();
+    }
     let mut exp_port = port.0.lock().unwrap();
     let mut buffer: String = String::new();
     let _ = exp_port.read_to_string(&mut buffer);
     if buffer.len() > 0 {
         trace!("Read {} bytes from EXP: {buffer}", buffer.len());
-        // TODO: right now there doesn't seem to be any use for data back from EXP bus
-        // so until there is this just logs it out
+        for line in lines.push(&buffer) {
+            match parse_exp(line, &mut queries.0) {
+                Ok(event) => {
+                    ev_exp.write(event);
+                }
+                Err(e) => error!("Unexpected EXP message: {e}"),
+            }
+        }
+    }
+
+    // A board which isn't there may not answer at all
+    let expired = queries
+        .0
+        .iter()
+        .take_while(|(_, deadline)| *deadline <= time.elapsed())
+        .count();
+    for (address, _) in queries.0.drain(..expired) {
+        ev_exp.write(ExpBusEvent::BoardMissing {
+            address: address.to_string(),
+        });
     }
 }
 
+/// Ask the expansion board at an address to identify itself. `exp_read` sends the reply as
+/// an `ExpBusEvent`.
+pub fn exp_identify(
+    address: &'static str,
+    now: Duration,
+    port: &ResMut<ExpPort>,
+    queries: &mut ExpIdentityQueries,
+) {
+    queries.0.push_back((address, now + EXP_IDENTITY_TIMEOUT));
+    exp_write(format!("ID@{address}:"), port);
+}
+
 pub fn exp_write(data: String, port: &ResMut<ExpPort>) {
     let mut exp_port = port.0.lock().unwrap();
     match exp_port.write(format!("{}\r", data).as_bytes()) {
@@ -67,3 +197,118 @@ pub fn exp_write(data: String, port: &ResMut<ExpPort>) {
         Err(e) => error!("{:?}", e),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fast::mock_port::MockPort;
+    use bevy::time::TimeUpdateStrategy;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn it_picks_ports_by_processor() {
+        let identities = [
+            ("/dev/ttyACM0".to_string(), "DSP".to_string()),
+            ("/dev/ttyACM1".to_string(), "EXP".to_string()),
+            ("/dev/ttyACM2".to_string(), "NET".to_string()),
+        ];
+        assert_eq!(
+            pick_ports(&identities),
+            DiscoveredPorts {
+                io_port_path: Some("/dev/ttyACM2".to_string()),
+                exp_port_path: Some("/dev/ttyACM1".to_string()),
+            }
+        );
+        assert_eq!(pick_ports(&[]), DiscoveredPorts::default());
+    }
+
+    #[test]
+    fn it_reports_boards_which_never_answer() {
+        let port = MockPort::default();
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            250,
+        )));
+        app.insert_resource(ExpPort(Arc::new(Mutex::new(Box::new(port.clone())))));
+        app.init_resource::<ExpIdentityQueries>();
+        app.add_event::<ExpBusEvent>();
+        app.add_systems(Update, exp_read);
+        app.world_mut()
+            .resource_mut::<ExpIdentityQueries>()
+            .0
+            .extend([("48", Duration::ZERO), ("B4", EXP_IDENTITY_TIMEOUT)]);
+
+        // The Neutron answers, the board at B4 doesn't
+        port.respond("ID:EXP FP-CPU-2000 02.13\r");
+        let mut cursor = app.world().resource::<Events<ExpBusEvent>>().get_cursor();
+        app.update();
+        let events = app.world().resource::<Events<ExpBusEvent>>();
+        assert_eq!(cursor.read(events).count(), 1);
+
+        for _ in 0..4 {
+            app.update();
+        }
+        let events = app.world().resource::<Events<ExpBusEvent>>();
+        assert_eq!(
+            cursor.read(events).cloned().collect::<Vec<_>>(),
+            vec![ExpBusEvent::BoardMissing {
+                address: "B4".to_string()
+            }]
+        );
+        assert!(app.world().resource::<ExpIdentityQueries>().0.is_empty());
+    }
+
+    #[test]
+    fn it_stamps_switch_reports_as_they_are_read() {
+        let port = MockPort::default();
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            250,
+        )));
+        app.insert_resource(IoNetPort(Arc::new(Mutex::new(Box::new(port.clone())))));
+        app.add_event::<FastIoEvent>();
+        app.add_systems(FixedFirst, io_read);
+        app.update();
+
+        port.respond("-L:1A\r");
+        let mut cursor = app.world().resource::<Events<FastIoEvent>>().get_cursor();
+        app.update();
+        let read = app.world().resource::<Time<Real>>().elapsed();
+        let events = app.world().resource::<Events<FastIoEvent>>();
+        assert_eq!(
+            cursor.read(events).cloned().collect::<Vec<_>>(),
+            vec![FastIoEvent::SwitchClosed {
+                id: 0x1a,
+                time: read
+            }]
+        );
+    }
+
+    #[test]
+    fn it_drops_partial_messages_from_a_lost_port() {
+        let lost = MockPort::default();
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.insert_resource(IoNetPort(Arc::new(Mutex::new(Box::new(lost.clone())))));
+        app.add_event::<FastIoEvent>();
+        app.add_systems(Update, io_read);
+        lost.respond("-L:1");
+        app.update();
+
+        let port = MockPort::default();
+        app.insert_resource(IoNetPort(Arc::new(Mutex::new(Box::new(port.clone())))));
+        port.respond("/L:03\r");
+        let mut cursor = app.world().resource::<Events<FastIoEvent>>().get_cursor();
+        app.update();
+        let events = app.world().resource::<Events<FastIoEvent>>();
+        assert_eq!(
+            cursor
+                .read(events)
+                .map(|event| event.to_string())
+                .collect::<Vec<_>>(),
+            vec!["/L:03"]
+        );
+    }
+}
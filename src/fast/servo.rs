@@ -0,0 +1,257 @@
+use bevy::{prelude::*, time::common_conditions::on_timer};
+use std::{collections::HashMap, time::Duration};
+
+use crate::rgb_led::{
+    Animatable, AnimationApplicator, AnimationStage, blend_f32, render_all_animatable,
+};
+
+use super::{ExpansionBoard, connection::FastConnection, resources::ExpPort, serial::exp_write};
+
+/// Servo - A hobby servo on an expansion board's servo header
+///
+/// Changes are sent to the board the way `RgbLed` changes are, and an `Animatable<f32, Servo>`
+/// is added to every servo so it can follow an `Animation<f32>` of positions.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct Servo {
+    pub board: ExpansionBoard,
+    /// Servo header on the expansion board, starting at `0`
+    pub port: u8,
+    /// Pulse width at position `0.0`, in microseconds
+    pub min_pulse: u16,
+    /// Pulse width at position `1.0`, in microseconds
+    pub max_pulse: u16,
+    /// Fraction of the full range to move per second. `0.0` moves as fast as the servo can
+    pub speed: f32,
+    /// From `0.0` at `min_pulse` to `1.0` at `max_pulse`
+    pub position: f32,
+}
+
+impl Default for Servo {
+    fn default() -> Self {
+        Self {
+            board: ExpansionBoard::default(),
+            port: 0,
+            min_pulse: 1000,
+            max_pulse: 2000,
+            speed: 0.,
+            position: 0.5,
+        }
+    }
+}
+
+impl Servo {
+    /// Position as sent to the board, from `00` to `FF`
+    fn level(&self) -> u8 {
+        (self.position.clamp(0., 1.) * 255.).round() as u8
+    }
+}
+
+#[allow(non_upper_case_globals)]
+pub const ServoPositionApplicator: AnimationApplicator<f32, Servo> =
+    |phase: f32, stage: &AnimationStage<f32>, component: &mut Servo| {
+        let stage_phase = stage.curve.sample(phase);
+        component.position = blend_f32(stage.from, stage.to, stage_phase);
+    };
+
+impl Animatable<f32, Servo> {
+    pub fn position() -> Self {
+        Self::new(ServoPositionApplicator)
+    }
+}
+
+/// ExpansionServos - Keeps the servos on the expansion boards in sync with `Servo` components
+pub struct ExpansionServos {
+    /// How frequently to send out servo moves; given in Hz/FPS
+    pub update_hz: f32,
+}
+
+impl Default for ExpansionServos {
+    fn default() -> Self {
+        Self { update_hz: 30. }
+    }
+}
+
+impl Plugin for ExpansionServos {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SentServos>();
+        app.add_observer(on_add_servo);
+        app.add_systems(Update, render_all_animatable::<f32, Servo>);
+        app.add_systems(
+            FixedLast,
+            servo_change_listener
+                .run_if(in_state(FastConnection::Connected))
+                .run_if(on_timer(Duration::from_secs_f32(1. / self.update_hz))),
+        );
+
+        // Like the LEDs, the boards forget their servos when they reset
+        app.add_systems(OnEnter(FastConnection::Connected), resend_servos);
+    }
+}
+
+/// Pulse range and position last sent to each servo header, so only what changed is sent
+/// again. Keyed by header rather than entity, as the board keeps its settings when a servo
+/// entity is despawned.
+#[derive(Resource, Debug, Default)]
+struct SentServos(HashMap<ServoHeader, ((u16, u16), u8)>);
+
+/// Expansion address and servo port
+type ServoHeader = (&'static str, u8);
+
+fn on_add_servo(trigger: Trigger<OnAdd, Servo>, mut commands: Commands) {
+    commands
+        .entity(trigger.target())
+        .insert(Animatable::position());
+}
+
+fn resend_servos(mut query: Query<&mut Servo>, mut sent: ResMut<SentServos>) {
+    sent.0.clear();
+    for mut servo in &mut query {
+        servo.set_changed();
+    }
+}
+
+fn servo_change_listener(
+    query: Query<&Servo, Changed<Servo>>,
+    mut sent: ResMut<SentServos>,
+    port: ResMut<ExpPort>,
+) {
+    let mut frames = vec![];
+    for servo in &query {
+        let address = servo.board.as_str();
+        let pulses = (servo.min_pulse, servo.max_pulse);
+        let level = servo.level();
+        let previous = sent.0.insert((address, servo.port), (pulses, level));
+
+        // Settings: servo, min and max pulse, and the position to start at
+        if previous.map(|(pulses, _)| pulses) != Some(pulses) {
+            frames.push(format!(
+                "MS@{address}:{},{:X},{:X},{level:X}",
+                servo.port, servo.min_pulse, servo.max_pulse
+            ));
+        }
+
+        // Move: servo, position and how long to take getting there
+        let from = match previous {
+            Some((previous_pulses, from)) if previous_pulses == pulses => from,
+            _ => continue,
+        };
+        if from != level {
+            let distance = from.abs_diff(level) as f32 / 255.;
+            let time = match servo.speed > 0. {
+                true => Duration::from_secs_f32(distance / servo.speed),
+                false => Duration::ZERO,
+            };
+            frames.push(format!(
+                "MP@{address}:{},{level:X},{:X}",
+                servo.port,
+                time.as_millis()
+            ));
+        }
+    }
+
+    // Send every frame in a single write
+    if !frames.is_empty() {
+        exp_write(frames.join("\r"), &port);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fast::mock_port::MockPort, rgb_led::Animation, rgb_led::Curve};
+    use bevy::time::TimeUpdateStrategy;
+    use std::sync::{Arc, Mutex};
+
+    fn app(port: &MockPort) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            100,
+        )));
+        app.insert_resource(ExpPort(Arc::new(Mutex::new(Box::new(port.clone())))));
+        app.init_resource::<SentServos>();
+        app.add_observer(on_add_servo);
+        app.add_systems(
+            Update,
+            (render_all_animatable::<f32, Servo>, servo_change_listener).chain(),
+        );
+        app
+    }
+
+    #[test]
+    fn it_sends_settings_then_moves() {
+        let port = MockPort::default();
+        let mut app = app(&port);
+        let servo = app
+            .world_mut()
+            .spawn(Servo {
+                board: ExpansionBoard::FpExp0091 {
+                    jumper_0: false,
+                    jumper_1: false,
+                },
+                port: 1,
+                speed: 2.,
+                position: 0.,
+                ..Default::default()
+            })
+            .id();
+        app.update();
+        assert_eq!(port.written(), vec!["MS@88:1,3E8,7D0,0"]);
+
+        // Half the range at two ranges a second
+        app.world_mut().get_mut::<Servo>(servo).unwrap().position = 0.5;
+        app.update();
+        app.update();
+        assert_eq!(port.written(), vec!["MS@88:1,3E8,7D0,0", "MP@88:1,80,FA"]);
+    }
+
+    #[test]
+    fn it_remembers_servos_by_header() {
+        let port = MockPort::default();
+        let mut app = app(&port);
+        let servo = app.world_mut().spawn(Servo::default()).id();
+        app.update();
+        app.world_mut().despawn(servo);
+
+        // The board still has the settings, so a new servo on the header only moves
+        app.world_mut().spawn(Servo {
+            position: 1.,
+            ..Default::default()
+        });
+        app.update();
+        assert_eq!(port.written(), vec!["MS@48:0,3E8,7D0,80", "MP@48:0,FF,0"]);
+        assert_eq!(app.world().resource::<SentServos>().0.len(), 1);
+    }
+
+    #[test]
+    fn it_follows_an_animation() {
+        let port = MockPort::default();
+        let mut app = app(&port);
+        let servo = app
+            .world_mut()
+            .spawn(Servo {
+                position: 0.,
+                ..Default::default()
+            })
+            .id();
+        app.update();
+
+        app.world_mut()
+            .get_mut::<Animatable<f32, Servo>>(servo)
+            .unwrap()
+            .enqueue_and_play(Animation::tween(vec![
+                (0., Duration::from_millis(400), Curve::Linear),
+                (1., Duration::ZERO, Curve::Linear),
+            ]));
+        for _ in 0..3 {
+            app.update();
+        }
+        let position = app.world().get::<Servo>(servo).unwrap().position;
+        assert!(position > 0. && position < 1., "{position}");
+        assert!(
+            port.written()
+                .iter()
+                .any(|frame| frame.starts_with("MP@48:0,"))
+        );
+    }
+}
@@ -0,0 +1,675 @@
+use bevy::prelude::*;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::{self, Read, Write},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::{
+    command::IoCommand,
+    controller::FastController,
+    exp_led_port::FastExpansionDevice,
+    framing::LineBuffer,
+    io_board::IoBoard,
+    parser::{FastIoEvent, NodeInfo},
+    transport::Transport,
+};
+
+/// SimulatedNeutron - An in-memory stand-in for the Neutron board
+///
+/// Answers the startup handshake, watchdog and switch state requests on the IO/NET bus,
+/// and identity requests and LED colors on the EXP bus. The watchdog runs out in real
+/// time unless it's refreshed. It answers as a Neutron unless given another controller.
+/// Only the Neutron's own expansion address, `48`, has a board until others are added.
+/// Clones share the same board, so a test can hand one to the `Neutron` plugin and keep
+/// another to close switches and inspect the LEDs.
+#[derive(Debug, Default, Clone)]
+pub struct SimulatedNeutron {
+    board: Arc<Mutex<SimulatedBoard>>,
+}
+
+/// Expansion address, port and index of an LED
+type LedKey = (String, u8, u8);
+
+#[derive(Debug, Default)]
+struct SimulatedBoard {
+    disconnected: bool,
+    /// The controller the board answers as
+    controller: FastController,
+    /// Boards on the IO loop, which answer `NN:`
+    io_boards: Vec<IoBoard>,
+    /// State of every switch, `true` when closed
+    switches: Vec<bool>,
+    /// When the watchdog runs out, unless it's refreshed first
+    watchdog_expires: Option<Instant>,
+    /// Every command received on the IO/NET bus, in order
+    io_commands: Vec<IoCommand>,
+    /// Latest color of every LED, keyed by expansion address, port and index
+    leds: HashMap<LedKey, Srgba>,
+    /// Addresses of the expansion boards on the EXP bus, besides the Neutron's
+    expansion_boards: HashSet<String>,
+    io: SimulatedBus,
+    exp: SimulatedBus,
+}
+
+#[derive(Debug, Default)]
+struct SimulatedBus {
+    lines: LineBuffer,
+    unread: VecDeque<u8>,
+}
+
+impl SimulatedBus {
+    fn respond(&mut self, message: impl std::fmt::Display) {
+        self.unread.extend(format!("{message}\r").as_bytes());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bus {
+    IoNet,
+    Exp,
+}
+
+impl SimulatedNeutron {
+    /// A board with the given number of switches, all open
+    pub fn new(switch_count: usize) -> Self {
+        let simulator = Self::default();
+        simulator.board().switches = vec![false; switch_count];
+        simulator
+    }
+
+    /// The IO/NET port of the board
+    pub fn io_port(&self) -> SimulatedPort {
+        SimulatedPort {
+            board: self.board.clone(),
+            bus: Bus::IoNet,
+        }
+    }
+
+    /// The EXP port of the board
+    pub fn exp_port(&self) -> SimulatedPort {
+        SimulatedPort {
+            board: self.board.clone(),
+            bus: Bus::Exp,
+        }
+    }
+
+    /// Close a switch and report it on the IO/NET bus
+    pub fn close_switch(&self, id: u16) {
+        self.set_switch(id, true);
+    }
+
+    /// Open a switch and report it on the IO/NET bus
+    pub fn open_switch(&self, id: u16) {
+        self.set_switch(id, false);
+    }
+
+    /// Whether a switch is currently closed
+    pub fn switch_closed(&self, id: u16) -> bool {
+        self.board()
+            .switches
+            .get(id as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Makes both ports behave as if the board was unplugged, or plugged back in
+    pub fn set_connected(&self, connected: bool) {
+        self.board().disconnected = !connected;
+    }
+
+    /// Answer as a different controller than the Neutron
+    pub fn set_controller(&self, controller: FastController) {
+        self.board().controller = controller;
+    }
+
+    /// Set the boards on the IO loop, in loop order
+    pub fn set_io_boards(&self, boards: Vec<IoBoard>) {
+        self.board().io_boards = boards;
+    }
+
+    /// Add an expansion board, e.g. `B4` for an FP-EXP-0071 without jumpers
+    pub fn add_expansion_board(&self, address: &str) {
+        self.board().expansion_boards.insert(address.to_uppercase());
+    }
+
+    /// Latest color written to an LED
+    pub fn led(&self, device: &FastExpansionDevice) -> Option<Srgba> {
+        let key = (
+            device.expansion_address.to_string(),
+            device.port,
+            device.index,
+        );
+        self.board().leds.get(&key).copied()
+    }
+
+    /// Every command received on the IO/NET bus so far
+    pub fn io_commands(&self) -> Vec<IoCommand> {
+        self.board().io_commands.clone()
+    }
+
+    /// Every command received on the IO/NET bus since the last call
+    pub fn take_io_commands(&self) -> Vec<IoCommand> {
+        std::mem::take(&mut self.board().io_commands)
+    }
+
+    fn set_switch(&self, id: u16, closed: bool) {
+        let mut board = self.board();
+        let id_index = id as usize;
+        if board.switches.len() <= id_index {
+            board.switches.resize(id_index + 1, false);
+        }
+        board.switches[id_index] = closed;
+        let event = match closed {
+            true => FastIoEvent::SwitchClosed {
+                id,
+                time: Duration::ZERO,
+            },
+            false => FastIoEvent::SwitchOpened {
+                id,
+                time: Duration::ZERO,
+            },
+        };
+        board.io.respond(event);
+    }
+
+    fn board(&self) -> std::sync::MutexGuard<'_, SimulatedBoard> {
+        self.board.lock().unwrap()
+    }
+}
+
+impl SimulatedBoard {
+    fn receive_io(&mut self, message: &str) {
+        let Ok(command) = IoCommand::from_str(message) else {
+            self.io.respond("XX:F");
+            return;
+        };
+        let passed = |command: &str| FastIoEvent::Passed {
+            command: command.to_string(),
+        };
+        let response = match &command {
+            IoCommand::Id => FastIoEvent::Identity {
+                processor: "NET".to_string(),
+                product: self.controller.product().to_string(),
+                firmware: "02.13".to_string(),
+            },
+            // Only the hardware the controller runs is accepted
+            IoCommand::ConfigureHardware { .. } => match self.controller.hardware() {
+                Some(hardware) if hardware == command => passed("CH"),
+                _ => FastIoEvent::Failed {
+                    command: "CH".to_string(),
+                },
+            },
+            IoCommand::SwitchStates => FastIoEvent::SwitchStates(self.switches.clone()),
+            IoCommand::Watchdog(Some(timeout)) => {
+                self.watchdog_expires = Some(Instant::now() + *timeout);
+                passed("WD")
+            }
+            IoCommand::Watchdog(None) => FastIoEvent::WatchdogRemaining(
+                self.watchdog_expires.map_or(Duration::ZERO, |expires| {
+                    expires.saturating_duration_since(Instant::now())
+                }),
+            ),
+            IoCommand::SwitchConfig(_) => passed("SL"),
+            IoCommand::DriverConfig(_) => passed("DL"),
+            IoCommand::DriverTrigger { .. } => passed("TL"),
+            IoCommand::NodeInfo { node } => match self.io_boards.get(*node as usize) {
+                Some(board) => FastIoEvent::NodeInfo(NodeInfo {
+                    node: *node,
+                    model: format!("{}-2", board.product()),
+                    firmware: "01.05".to_string(),
+                    drivers: board.coil_port_count(),
+                    switches: board.switch_port_count(),
+                }),
+                None => FastIoEvent::Failed {
+                    command: "NN".to_string(),
+                },
+            },
+        };
+        self.io_commands.push(command);
+        self.io.respond(response);
+    }
+
+    fn receive_exp(&mut self, message: &str) {
+        let address = message
+            .split_once('@')
+            .and_then(|(_, target)| target.get(..2));
+        let board = address
+            .filter(|address| *address == "48" || self.expansion_boards.contains(*address))
+            .and_then(expansion_product);
+        if message == "ID:" {
+            self.exp.respond("ID:EXP FP-CPU-2000 02.13");
+        } else if message.starts_with("ID@") {
+            match board {
+                Some(product) => self.exp.respond(format!("ID:EXP {product} 0.10")),
+                None => self.exp.respond("ID:F"),
+            }
+        } else if let Some(colors) = parse_led_colors(message) {
+            match board {
+                Some(_) => self.leds.extend(colors),
+                None => self.exp.respond("RS:F"),
+            }
+        } else {
+            trace!("Simulated EXP bus ignored {message}");
+        }
+    }
+}
+
+/// Product name of the expansion board at an address
+fn expansion_product(address: &str) -> Option<&'static str> {
+    match address {
+        "48" => Some("FP-CPU-2000"),
+        "B4" | "B5" | "B6" | "B7" => Some("FP-EXP-0071"),
+        "84" | "85" | "86" | "87" => Some("FP-EXP-0081"),
+        "88" | "89" | "8A" | "8B" => Some("FP-EXP-0091"),
+        _ => None,
+    }
+}
+
+/// Reads an `RS@<address><port>:<index><rrggbb>[,<index><rrggbb>...]` LED color message
+fn parse_led_colors(message: &str) -> Option<Vec<(LedKey, Srgba)>> {
+    let (target, data) = message.strip_prefix("RS@")?.split_once(':')?;
+    let address = target.get(..2)?;
+    let port = target.get(2..)?.parse().ok()?;
+    data.split(',')
+        .map(|item| {
+            let (index, color) = item.split_at_checked(item.len().checked_sub(6)?)?;
+            let color = Srgba::hex(color).ok()?;
+            Some(((address.to_string(), port, index.parse().ok()?), color))
+        })
+        .collect()
+}
+
+/// One port of a `SimulatedNeutron`
+#[derive(Debug, Clone)]
+pub struct SimulatedPort {
+    board: Arc<Mutex<SimulatedBoard>>,
+    bus: Bus,
+}
+
+impl SimulatedPort {
+    fn check_connected(board: &SimulatedBoard) -> io::Result<()> {
+        match board.disconnected {
+            true => Err(io::ErrorKind::BrokenPipe.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Read for SimulatedPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut board = self.board.lock().unwrap();
+        Self::check_connected(&board)?;
+        let unread = match self.bus {
+            Bus::IoNet => &mut board.io.unread,
+            Bus::Exp => &mut board.exp.unread,
+        };
+        if unread.is_empty() {
+            // Like a real port, reading with nothing available times out
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        let count = buf.len().min(unread.len());
+        for (i, byte) in unread.drain(..count).enumerate() {
+            buf[i] = byte;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for SimulatedPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut board = self.board.lock().unwrap();
+        Self::check_connected(&board)?;
+        let data = String::from_utf8_lossy(buf);
+        match self.bus {
+            Bus::IoNet => {
+                for message in board.io.lines.push(&data) {
+                    board.receive_io(&message);
+                }
+            }
+            Bus::Exp => {
+                for message in board.exp.lines.push(&data) {
+                    board.receive_exp(&message);
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for SimulatedPort {
+    fn is_connected(&self) -> bool {
+        !self.board.lock().unwrap().disconnected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fast::{
+            ExpBusEvent, ExpansionBoard, ExpansionLeds, FastConnection, FastError, FastSwitches,
+            IoBoard, IoLoopChecked, IoSwitch, LedDefinition, LoopMismatch, Neutron, RetroPlatform,
+            SwitchConfig, SwitchDefinition, SwitchMode, SwitchStatesInitialized,
+        },
+        pinball::{CabinetButtons, PinballBase, RgbLed},
+    };
+    use bevy::{state::app::StatesPlugin, time::TimeUpdateStrategy};
+    use std::{thread, time::Instant};
+
+    fn io_boards() -> Vec<IoBoard> {
+        vec![
+            IoBoard::CabinetIO {
+                switches: vec![],
+                coils: vec![],
+            },
+            IoBoard::Fast3208 {
+                switches: vec![Some(IoSwitch {
+                    name: "trough_1",
+                    normally_closed: true,
+                    ..Default::default()
+                })],
+                coils: vec![],
+            },
+        ]
+    }
+
+    /// An app configured with `io_boards()`, which the simulator has unless the test
+    /// changes them
+    fn app_with_simulator(simulator: &SimulatedNeutron) -> App {
+        if simulator.board().io_boards.is_empty() {
+            simulator.set_io_boards(io_boards());
+        }
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin, PinballBase::default()));
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            50,
+        )));
+        app.add_plugins(Neutron {
+            io_boards: io_boards(),
+            simulator: Some(simulator.clone()),
+            ..Default::default()
+        });
+        app.add_plugins(ExpansionLeds {
+            leds: vec![LedDefinition {
+                board: ExpansionBoard::Neutron,
+                port: 0,
+                index: 3,
+                name: "LED3",
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        app.add_plugins(FastSwitches {
+            switches: vec![SwitchDefinition {
+                id: CabinetButtons::LeftFlipper,
+                board: 0,
+                port: 2,
+            }],
+        });
+        app
+    }
+
+    /// Run the app until the background handshake with the simulator completes
+    fn connect(app: &mut App) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while *app.world().resource::<State<FastConnection>>().get() != FastConnection::Connected
+        {
+            assert!(Instant::now() < deadline, "Never connected to the simulator");
+            app.update();
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn it_answers_the_startup_handshake() {
+        let simulator = SimulatedNeutron::new(8);
+        let mut app = app_with_simulator(&simulator);
+        connect(&mut app);
+        app.update();
+
+        let commands = simulator.io_commands();
+        assert_eq!(commands[0], IoCommand::Id);
+        assert!(commands.contains(&IoCommand::Watchdog(Some(Duration::from_secs(1)))));
+    }
+
+    #[test]
+    fn it_connects_to_each_controller() {
+        let board = |platform| IoBoard::Retro {
+            platform,
+            switches: vec![],
+            coils: vec![],
+        };
+        let wpc = RetroPlatform::Wpc95;
+        let controllers = [
+            (FastController::Neutron, io_boards(), 2),
+            (FastController::Nano, io_boards(), 2),
+            (FastController::Retro(wpc), vec![board(wpc)], 20),
+        ];
+        for (controller, io_boards, switch) in controllers {
+            let simulator = SimulatedNeutron::new(72);
+            simulator.set_controller(controller);
+            simulator.set_io_boards(io_boards.clone());
+            let mut app = App::new();
+            app.add_plugins((MinimalPlugins, StatesPlugin, PinballBase::default()));
+            app.add_plugins(Neutron {
+                controller,
+                io_boards,
+                simulator: Some(simulator.clone()),
+                ..Default::default()
+            });
+            // WPC switch 35 is the 21st in the matrix
+            let port = match controller {
+                FastController::Retro(platform) => platform.matrix_switch(35).unwrap(),
+                _ => 2,
+            };
+            app.add_plugins(FastSwitches {
+                switches: vec![SwitchDefinition {
+                    id: CabinetButtons::LeftFlipper,
+                    board: 0,
+                    port,
+                }],
+            });
+            connect(&mut app);
+            simulator.close_switch(switch);
+            for _ in 0..3 {
+                app.update();
+                thread::sleep(Duration::from_millis(10));
+            }
+
+            let commands = simulator.io_commands();
+            assert_eq!(commands[0], IoCommand::Id, "{controller}");
+            let hardware = commands
+                .iter()
+                .find(|command| matches!(command, IoCommand::ConfigureHardware { .. }));
+            assert_eq!(hardware, controller.hardware().as_ref(), "{controller}");
+            let buttons = app.world().resource::<ButtonInput<CabinetButtons>>();
+            assert!(buttons.pressed(CabinetButtons::LeftFlipper), "{controller}");
+        }
+    }
+
+    #[test]
+    fn it_fails_to_connect_to_the_wrong_controller() {
+        let simulator = SimulatedNeutron::new(8);
+        simulator.set_controller(FastController::Nano);
+        let mut app = app_with_simulator(&simulator);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while *app.world().resource::<State<FastConnection>>().get() == FastConnection::Connecting {
+            assert!(Instant::now() < deadline, "Never finished connecting");
+            app.update();
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(
+            *app.world().resource::<State<FastConnection>>().get(),
+            FastConnection::Failed(FastError::WrongController {
+                expected: "FP-CPU-2000".to_string(),
+                found: "ID:NET FP-CPU-002 02.13".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn it_configures_switches_on_connecting() {
+        let simulator = SimulatedNeutron::new(56);
+        let mut app = app_with_simulator(&simulator);
+        connect(&mut app);
+        app.update();
+
+        assert!(
+            simulator
+                .io_commands()
+                .contains(&IoCommand::SwitchConfig(SwitchConfig {
+                    switch: 24,
+                    mode: SwitchMode::Inverted,
+                    debounce_close: 2,
+                    debounce_open: 2,
+                }))
+        );
+    }
+
+    #[test]
+    fn it_seeds_switches_on_connecting() {
+        let simulator = SimulatedNeutron::new(56);
+        // Closed before the game started, so never reported as a change
+        simulator.board().switches[2] = true;
+        let mut app = app_with_simulator(&simulator);
+        let mut cursor = app
+            .world()
+            .resource::<Events<SwitchStatesInitialized>>()
+            .get_cursor();
+        connect(&mut app);
+        let mut initialized = 0;
+        for _ in 0..3 {
+            app.update();
+            let sent = app.world().resource::<Events<SwitchStatesInitialized>>();
+            initialized += cursor.read(sent).count();
+        }
+
+        assert_eq!(initialized, 1);
+        let buttons = app.world().resource::<ButtonInput<CabinetButtons>>();
+        assert!(buttons.pressed(CabinetButtons::LeftFlipper));
+    }
+
+    #[test]
+    fn it_receives_led_colors() {
+        let simulator = SimulatedNeutron::new(8);
+        let mut app = app_with_simulator(&simulator);
+        connect(&mut app);
+
+        let mut leds = app.world_mut().query::<&mut RgbLed>();
+        for mut led in leds.iter_mut(app.world_mut()) {
+            led.color = Srgba::rgb(1., 0., 0.);
+        }
+        app.update();
+        app.update();
+
+        let device = FastExpansionDevice {
+            expansion_address: "48",
+            port: 0,
+            index: 3,
+        };
+        assert_eq!(simulator.led(&device), Some(Srgba::rgb(1., 0., 0.)));
+    }
+
+    #[test]
+    fn it_reports_missing_expansion_boards() {
+        let simulator = SimulatedNeutron::new(8);
+        simulator.add_expansion_board("b4");
+        let mut app = app_with_simulator(&simulator);
+        for expansion_address in ["B4", "88"] {
+            app.world_mut().spawn((
+                RgbLed::default(),
+                FastExpansionDevice {
+                    expansion_address,
+                    port: 0,
+                    index: 0,
+                },
+            ));
+        }
+        connect(&mut app);
+        let mut cursor = app.world().resource::<Events<ExpBusEvent>>().get_cursor();
+        let mut events = vec![];
+        for _ in 0..2 {
+            app.update();
+            let sent = app.world().resource::<Events<ExpBusEvent>>();
+            events.extend(cursor.read(sent).cloned());
+        }
+        let identity = |address: &str, product: &str| ExpBusEvent::Identity {
+            address: address.to_string(),
+            processor: "EXP".to_string(),
+            product: product.to_string(),
+            firmware: "0.10".to_string(),
+        };
+        assert_eq!(
+            events[..3],
+            [
+                identity("48", "FP-CPU-2000"),
+                ExpBusEvent::BoardMissing {
+                    address: "88".to_string()
+                },
+                identity("B4", "FP-EXP-0071"),
+            ]
+        );
+        // The LEDs sent to the missing board fail too
+        assert!(events.contains(&ExpBusEvent::Failed {
+            command: "RS".to_string()
+        }));
+    }
+
+    #[test]
+    fn it_reports_swapped_io_boards() {
+        let simulator = SimulatedNeutron::new(8);
+        simulator.set_io_boards(io_boards().into_iter().rev().collect());
+        let mut app = app_with_simulator(&simulator);
+        connect(&mut app);
+        let mut cursor = app.world().resource::<Events<IoLoopChecked>>().get_cursor();
+        let mut checks = vec![];
+        for _ in 0..3 {
+            app.update();
+            let sent = app.world().resource::<Events<IoLoopChecked>>();
+            checks.extend(cursor.read(sent).cloned());
+        }
+
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].nodes.len(), 2);
+        assert_eq!(
+            checks[0].mismatches,
+            vec![LoopMismatch::Swapped {
+                first: 0,
+                second: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn it_reports_injected_switches() {
+        let simulator = SimulatedNeutron::new(8);
+        let mut app = app_with_simulator(&simulator);
+        connect(&mut app);
+
+        simulator.close_switch(2);
+        app.update();
+        app.update();
+        let buttons = app.world().resource::<ButtonInput<CabinetButtons>>();
+        assert!(buttons.pressed(CabinetButtons::LeftFlipper));
+    }
+
+    #[test]
+    fn it_parses_led_colors() {
+        assert_eq!(
+            parse_led_colors("RS@B41:12ff8000,3000000"),
+            Some(vec![
+                (("B4".to_string(), 1, 12), Srgba::rgb_u8(255, 128, 0)),
+                (("B4".to_string(), 1, 3), Srgba::rgb_u8(0, 0, 0)),
+            ])
+        );
+        assert_eq!(parse_led_colors("RS@480:"), None);
+    }
+}
@@ -0,0 +1,485 @@
+use bevy::prelude::*;
+use std::time::Duration;
+
+use super::{FastIoEvent, connection::FastConnection, resources::ExpPort, serial::exp_write};
+
+/// Stepper - A stepper motor on an expansion board's stepper header
+///
+/// Steppers are homed once the Neutron connects and has read every switch, or as soon as
+/// they're spawned while it's connected, by running them in reverse until their home switch
+/// closes. A stepper already on its home switch is home without moving. After that,
+/// setting `target` moves the stepper there, and a `StepperMoveComplete` event is sent once
+/// it should have arrived. A target set mid-move is started once the current move completes.
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct Stepper {
+    /// Same addressing as `FastExpansionDevice`
+    pub expansion_address: &'static str,
+    /// Stepper header on the expansion board, starting at `0`
+    pub port: u8,
+    /// FAST switch number of the switch which closes at the home position
+    pub home_switch: u16,
+    /// Position to move to, in steps from home
+    pub target: i32,
+    /// Most steps per second
+    pub speed: u32,
+    /// Steps per second added or removed each second while speeding up or slowing down
+    pub acceleration: u32,
+    /// Longest time to wait for the home switch before giving up on homing
+    pub home_timeout: Duration,
+}
+
+impl Default for Stepper {
+    fn default() -> Self {
+        Self {
+            expansion_address: "48",
+            port: 0,
+            home_switch: 0,
+            target: 0,
+            speed: 200,
+            acceleration: 400,
+            home_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl Stepper {
+    /// Time a move takes, speeding up at `acceleration` until reaching `speed`, then
+    /// slowing down to stop on the target
+    pub fn move_time(&self, steps: u32) -> Duration {
+        let (distance, speed) = (steps as f32, self.speed.max(1) as f32);
+        let acceleration = self.acceleration.max(1) as f32;
+        let ramp_distance = speed * speed / acceleration;
+        let seconds = match distance >= ramp_distance {
+            true => distance / speed + speed / acceleration,
+            false => 2. * (distance / acceleration).sqrt(),
+        };
+        Duration::from_secs_f32(seconds)
+    }
+}
+
+/// Where a `Stepper` is, added alongside each one
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StepperStatus {
+    pub state: StepperState,
+    /// Steps from home, once homed
+    pub position: i32,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StepperState {
+    /// Waiting for the Neutron to connect and read the switches
+    #[default]
+    Unhomed,
+    /// Running in reverse until the home switch closes, giving up once `remaining` runs out
+    Homing {
+        remaining: Duration,
+    },
+    /// The home switch never closed, so the stepper won't move until the next (re)connect
+    HomingFailed,
+    Idle,
+    Moving {
+        to: i32,
+        remaining: Duration,
+    },
+}
+
+/// A stepper finished homing, at position `0`, or arrived at its target
+///
+/// Homing is confirmed by the home switch, but the boards don't report when a move ends,
+/// so arrival is estimated from the move time for the stepper's speed and acceleration.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepperMoveComplete {
+    pub stepper: Entity,
+    pub position: i32,
+}
+
+/// A stepper's home switch didn't close within its `home_timeout`
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepperHomingFailed {
+    pub stepper: Entity,
+}
+
+/// ExpansionSteppers - Homes and moves the steppers on the expansion boards
+pub struct ExpansionSteppers;
+
+impl Plugin for ExpansionSteppers {
+    fn build(&self, app: &mut App) {
+        app.add_event::<StepperMoveComplete>();
+        app.add_event::<StepperHomingFailed>();
+        app.init_resource::<KnownSwitchStates>();
+        app.add_observer(on_add_stepper);
+        app.add_systems(OnEnter(FastConnection::Connected), unhome_steppers);
+        app.add_systems(
+            Update,
+            (home_switch_listener, home_steppers, move_steppers)
+                .chain()
+                .run_if(in_state(FastConnection::Connected)),
+        );
+    }
+}
+
+fn on_add_stepper(trigger: Trigger<OnAdd, Stepper>, mut commands: Commands) {
+    commands
+        .entity(trigger.target())
+        .insert(StepperStatus::default());
+}
+
+/// State of every switch, `true` when closed, once the `SA:` sent after connecting has
+/// been answered. Kept up to date with switch reports.
+#[derive(Resource, Debug, Default)]
+struct KnownSwitchStates(Option<Vec<bool>>);
+
+/// The position is lost whenever a board resets, so home every stepper after a (re)connect
+fn unhome_steppers(mut query: Query<&mut StepperStatus>, mut switches: ResMut<KnownSwitchStates>) {
+    for mut status in &mut query {
+        *status = StepperStatus::default();
+    }
+    switches.0 = None;
+}
+
+fn home_steppers(
+    mut query: Query<(Entity, &Stepper, &mut StepperStatus)>,
+    switches: Res<KnownSwitchStates>,
+    mut ev: EventWriter<StepperMoveComplete>,
+    port: ResMut<ExpPort>,
+) {
+    // A stepper on its home switch never sees it close, so wait to know where they are
+    let Some(switches) = &switches.0 else {
+        return;
+    };
+    for (entity, stepper, mut status) in &mut query {
+        if status.state != StepperState::Unhomed {
+            continue;
+        }
+        let (address, index) = (stepper.expansion_address, stepper.port);
+        // Settings: stepper, speed and acceleration
+        exp_write(
+            format!(
+                "MC@{address}:{index},{:X},{:X}",
+                stepper.speed, stepper.acceleration
+            ),
+            &port,
+        );
+        if switches
+            .get(stepper.home_switch as usize)
+            .is_some_and(|closed| *closed)
+        {
+            // Running in reverse would only drive it into the end of its travel
+            *status = StepperStatus {
+                state: StepperState::Idle,
+                position: 0,
+            };
+            ev.write(StepperMoveComplete {
+                stepper: entity,
+                position: 0,
+            });
+            continue;
+        }
+        // Home: run in reverse until stopped
+        exp_write(format!("MH@{address}:{index}"), &port);
+        status.state = StepperState::Homing {
+            remaining: stepper.home_timeout,
+        };
+    }
+}
+
+fn home_switch_listener(
+    mut query: Query<(Entity, &Stepper, &mut StepperStatus)>,
+    mut ev_io: EventReader<FastIoEvent>,
+    mut switches: ResMut<KnownSwitchStates>,
+    mut ev: EventWriter<StepperMoveComplete>,
+    port: ResMut<ExpPort>,
+) {
+    for event in ev_io.read() {
+        match (event, &mut switches.0) {
+            (FastIoEvent::SwitchStates(states), known) => *known = Some(states.clone()),
+            (FastIoEvent::SwitchClosed { id, .. }, Some(known)) => {
+                if let Some(closed) = known.get_mut(*id as usize) {
+                    *closed = true;
+                }
+            }
+            (FastIoEvent::SwitchOpened { id, .. }, Some(known)) => {
+                if let Some(closed) = known.get_mut(*id as usize) {
+                    *closed = false;
+                }
+            }
+            _ => {}
+        }
+        for (entity, stepper, mut status) in &mut query {
+            let home = match event {
+                FastIoEvent::SwitchClosed { id, .. } => *id == stepper.home_switch,
+                FastIoEvent::SwitchStates(states) => states
+                    .get(stepper.home_switch as usize)
+                    .is_some_and(|closed| *closed),
+                _ => false,
+            };
+            if home && matches!(status.state, StepperState::Homing { .. }) {
+                // Stop
+                exp_write(
+                    format!("MX@{}:{}", stepper.expansion_address, stepper.port),
+                    &port,
+                );
+                *status = StepperStatus {
+                    state: StepperState::Idle,
+                    position: 0,
+                };
+                ev.write(StepperMoveComplete {
+                    stepper: entity,
+                    position: 0,
+                });
+            }
+        }
+    }
+}
+
+fn move_steppers(
+    mut query: Query<(Entity, &Stepper, &mut StepperStatus)>,
+    time: Res<Time>,
+    mut ev: EventWriter<StepperMoveComplete>,
+    mut ev_failed: EventWriter<StepperHomingFailed>,
+    port: ResMut<ExpPort>,
+) {
+    for (entity, stepper, mut status) in &mut query {
+        match status.state {
+            StepperState::Homing { remaining } => {
+                let remaining = remaining.saturating_sub(time.delta());
+                if remaining.is_zero() {
+                    // Stop, rather than leave it running into the end of its travel
+                    exp_write(
+                        format!("MX@{}:{}", stepper.expansion_address, stepper.port),
+                        &port,
+                    );
+                    error!(
+                        "Stepper {}:{} never reached home switch {}",
+                        stepper.expansion_address, stepper.port, stepper.home_switch
+                    );
+                    status.state = StepperState::HomingFailed;
+                    ev_failed.write(StepperHomingFailed { stepper: entity });
+                } else {
+                    status.state = StepperState::Homing { remaining };
+                }
+            }
+            StepperState::Idle if stepper.target != status.position => {
+                let steps = stepper.target.abs_diff(status.position);
+                // Move: stepper and number of steps, forward or in reverse
+                let direction = match stepper.target > status.position {
+                    true => "MF",
+                    false => "MR",
+                };
+                exp_write(
+                    format!(
+                        "{direction}@{}:{},{steps:X}",
+                        stepper.expansion_address, stepper.port
+                    ),
+                    &port,
+                );
+                status.state = StepperState::Moving {
+                    to: stepper.target,
+                    remaining: stepper.move_time(steps),
+                };
+            }
+            StepperState::Moving { to, remaining } => {
+                let remaining = remaining.saturating_sub(time.delta());
+                if remaining.is_zero() {
+                    *status = StepperStatus {
+                        state: StepperState::Idle,
+                        position: to,
+                    };
+                    ev.write(StepperMoveComplete {
+                        stepper: entity,
+                        position: to,
+                    });
+                } else {
+                    status.state = StepperState::Moving { to, remaining };
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fast::mock_port::MockPort;
+    use bevy::{state::app::StatesPlugin, time::TimeUpdateStrategy};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn it_times_moves_with_acceleration() {
+        let stepper = Stepper {
+            speed: 100,
+            acceleration: 200,
+            ..Default::default()
+        };
+        // Reaches full speed after 25 steps, then cruises
+        assert_eq!(stepper.move_time(150), Duration::from_secs_f32(2.));
+        // Never reaches full speed
+        assert_eq!(stepper.move_time(50), Duration::from_secs_f32(1.));
+    }
+
+    fn app(port: &MockPort) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin, ExpansionSteppers));
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            250,
+        )));
+        app.insert_resource(ExpPort(Arc::new(Mutex::new(Box::new(port.clone())))));
+        app.add_event::<FastIoEvent>();
+        app.insert_state(FastConnection::Connected);
+        app
+    }
+
+    /// Answer the `SA:` sent after connecting, with only the given switches closed
+    fn read_switches(app: &mut App, closed: &[u16]) {
+        let mut states = vec![false; 8];
+        for id in closed {
+            states[*id as usize] = true;
+        }
+        app.world_mut()
+            .send_event(FastIoEvent::SwitchStates(states));
+    }
+
+    #[test]
+    fn it_homes_then_moves_to_the_target() {
+        let port = MockPort::default();
+        let mut app = app(&port);
+        read_switches(&mut app, &[]);
+        let stepper = app
+            .world_mut()
+            .spawn(Stepper {
+                expansion_address: "B4",
+                port: 1,
+                home_switch: 5,
+                target: 100,
+                speed: 100,
+                acceleration: 200,
+                ..Default::default()
+            })
+            .id();
+        app.update();
+        assert_eq!(port.written(), vec!["MC@B4:1,64,C8", "MH@B4:1"]);
+
+        app.world_mut().send_event(FastIoEvent::SwitchClosed {
+            id: 5,
+            time: Duration::ZERO,
+        });
+        app.update();
+        assert_eq!(
+            port.written(),
+            vec!["MC@B4:1,64,C8", "MH@B4:1", "MX@B4:1", "MF@B4:1,64"]
+        );
+
+        // 100 steps take 1.5s
+        let completed = |app: &App| {
+            app.world()
+                .resource::<Events<StepperMoveComplete>>()
+                .iter_current_update_events()
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        for _ in 0..5 {
+            app.update();
+            assert!(completed(&app).is_empty());
+        }
+        app.update();
+        assert_eq!(
+            completed(&app),
+            vec![StepperMoveComplete {
+                stepper,
+                position: 100
+            }]
+        );
+        assert_eq!(
+            app.world().get::<StepperStatus>(stepper),
+            Some(&StepperStatus {
+                state: StepperState::Idle,
+                position: 100
+            })
+        );
+    }
+
+    #[test]
+    fn it_homes_steppers_added_once_connected() {
+        let port = MockPort::default();
+        let mut app = app(&port);
+        read_switches(&mut app, &[]);
+        app.update();
+        assert!(port.written().is_empty());
+
+        app.world_mut().spawn(Stepper::default());
+        app.update();
+        assert_eq!(port.written(), vec!["MC@48:0,C8,190", "MH@48:0"]);
+    }
+
+    #[test]
+    fn it_gives_up_homing_without_the_home_switch() {
+        let port = MockPort::default();
+        let mut app = app(&port);
+        read_switches(&mut app, &[]);
+        let stepper = app
+            .world_mut()
+            .spawn(Stepper {
+                home_timeout: Duration::from_secs(1),
+                ..Default::default()
+            })
+            .id();
+        let mut cursor = app
+            .world()
+            .resource::<Events<StepperHomingFailed>>()
+            .get_cursor();
+        for _ in 0..6 {
+            app.update();
+        }
+
+        let events = app.world().resource::<Events<StepperHomingFailed>>();
+        assert_eq!(
+            cursor.read(events).copied().collect::<Vec<_>>(),
+            vec![StepperHomingFailed { stepper }]
+        );
+        assert_eq!(port.written(), vec!["MC@48:0,C8,190", "MH@48:0", "MX@48:0"]);
+        assert_eq!(
+            app.world().get::<StepperStatus>(stepper).unwrap().state,
+            StepperState::HomingFailed
+        );
+    }
+
+    #[test]
+    fn it_waits_for_the_switches_before_homing() {
+        let port = MockPort::default();
+        let mut app = app(&port);
+        let stepper = app
+            .world_mut()
+            .spawn(Stepper {
+                home_switch: 3,
+                ..Default::default()
+            })
+            .id();
+        app.update();
+        assert!(port.written().is_empty());
+
+        // Already on its home switch, so it's home without running in reverse
+        read_switches(&mut app, &[3]);
+        let mut cursor = app
+            .world()
+            .resource::<Events<StepperMoveComplete>>()
+            .get_cursor();
+        app.update();
+        assert_eq!(port.written(), vec!["MC@48:0,C8,190"]);
+        let events = app.world().resource::<Events<StepperMoveComplete>>();
+        assert_eq!(
+            cursor.read(events).copied().collect::<Vec<_>>(),
+            vec![StepperMoveComplete {
+                stepper,
+                position: 0
+            }]
+        );
+        assert_eq!(
+            app.world().get::<StepperStatus>(stepper),
+            Some(&StepperStatus {
+                state: StepperState::Idle,
+                position: 0
+            })
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use bevy::prelude::*;
+use std::{collections::HashMap, hash::Hash, time::Duration};
+
+use crate::pinball::{SwitchInput, SwitchState, SwitchTimes};
+
+use super::{io_board::IoNetLoop, FastIoEvent};
+
+/// FastSwitches - Maps switches on the IO/NET loop to an input type
+///
+/// Switch reports from the Neutron drive the `ButtonInput<T>` resource and emit
+/// `SwitchInput<T>` events, exactly as the keyboard `SwitchEmulator` does. The
+/// `Neutron` plugin must be added first as it provides the IO board layout.
+pub struct FastSwitches<T: Copy + Eq + Hash + Send + Sync + 'static> {
+    pub switches: Vec<SwitchDefinition<T>>,
+}
+
+/// Configuration for a single switch
+#[derive(Debug, Clone)]
+pub struct SwitchDefinition<T: Copy + Eq + Hash + Send + Sync + 'static> {
+    pub id: T,
+    /// Position of the IO board in the loop. The board nearest the Neutron = `0`
+    pub board: usize,
+    /// Switch port on the IO board
+    pub port: u8,
+}
+
+impl<T: Copy + Eq + Hash + Send + Sync + 'static> Plugin for FastSwitches<T> {
+    fn build(&self, app: &mut App) {
+        let io_loop = app
+            .world()
+            .get_resource::<IoNetLoop>()
+            .cloned()
+            .unwrap_or_default();
+
+        let mut registry = HashMap::new();
+        for definition in self.switches.iter() {
+            match io_loop.switch_number(definition.board, definition.port) {
+                Some(number) => {
+                    registry.insert(number, definition.id);
+                }
+                None => error!(
+                    "No switch port {} on IO board {}",
+                    definition.port, definition.board
+                ),
+            }
+        }
+
+        app.init_resource::<ButtonInput<T>>();
+        app.add_event::<SwitchInput<T>>();
+        SwitchTimes::<T>::init(app);
+        app.insert_resource(SwitchRegistry(registry));
+        app.add_systems(Update, switch_listener::<T>.in_set(FastSwitchSystems));
+    }
+}
+
+/// The switch listeners of every `FastSwitches` input type. Systems ordered after this
+/// set see switch changes in the same frame they're reported.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FastSwitchSystems;
+
+/// Fired once every `ButtonInput<T>` of `FastSwitches` has been seeded with the switch
+/// states read after connecting
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwitchStatesInitialized;
+
+/// Lookup from FAST switch number to switch identifier
+#[derive(Resource, Debug)]
+pub struct SwitchRegistry<T: Copy + Eq + Hash + Send + Sync + 'static>(pub HashMap<u16, T>);
+
+fn switch_listener<T: Copy + Eq + Hash + Send + Sync + 'static>(
+    registry: Res<SwitchRegistry<T>>,
+    mut ev_io: EventReader<FastIoEvent>,
+    mut switches: ResMut<ButtonInput<T>>,
+    mut times: ResMut<SwitchTimes<T>>,
+    time: Res<Time<Real>>,
+    mut ev: EventWriter<SwitchInput<T>>,
+) {
+    for event in ev_io.read() {
+        let (number, state, read) = match event {
+            FastIoEvent::SwitchClosed { id, time } => (id, SwitchState::Closed, *time),
+            FastIoEvent::SwitchOpened { id, time } => (id, SwitchState::Open, *time),
+            FastIoEvent::SwitchStates(states) => {
+                seed_switches(&registry, states, time.elapsed(), &mut switches, &mut times);
+                continue;
+            }
+            _ => continue,
+        };
+        if let Some(switch_id) = registry.0.get(number) {
+            match state {
+                SwitchState::Closed => switches.press(*switch_id),
+                SwitchState::Open => switches.release(*switch_id),
+            }
+            let input = SwitchInput {
+                id: *switch_id,
+                state,
+                time: read,
+            };
+            times.record(&input);
+            ev.write(input);
+        }
+    }
+}
+
+/// Set each switch to its state in an `SA:` dump, without reporting it as a change. Switches
+/// the dump changes, or which have no time yet, are timed from `now`.
+fn seed_switches<T: Copy + Eq + Hash + Send + Sync + 'static>(
+    registry: &SwitchRegistry<T>,
+    states: &[bool],
+    now: Duration,
+    switches: &mut ButtonInput<T>,
+    times: &mut SwitchTimes<T>,
+) {
+    for (number, switch_id) in registry.0.iter() {
+        let Some(closed) = states.get(*number as usize) else {
+            continue;
+        };
+        let untimed =
+            times.last_closed(*switch_id).is_none() && times.last_opened(*switch_id).is_none();
+        match closed {
+            true if !switches.pressed(*switch_id) => {
+                switches.press(*switch_id);
+                switches.clear_just_pressed(*switch_id);
+            }
+            false if switches.pressed(*switch_id) => {
+                switches.release(*switch_id);
+                switches.clear_just_released(*switch_id);
+            }
+            _ if !untimed => continue,
+            _ => {}
+        }
+        times.record(&SwitchInput {
+            id: *switch_id,
+            state: match closed {
+                true => SwitchState::Closed,
+                false => SwitchState::Open,
+            },
+            time: now,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pinball::CabinetButtons;
+    use bevy::time::TimeUpdateStrategy;
+    use std::time::Duration;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            100,
+        )));
+        SwitchTimes::<CabinetButtons>::init(&mut app);
+        app.add_event::<FastIoEvent>();
+        app.insert_resource(SwitchRegistry(HashMap::from([(
+            0x1a,
+            CabinetButtons::StartButton,
+        )])));
+        app.init_resource::<ButtonInput<CabinetButtons>>();
+        app.add_event::<SwitchInput<CabinetButtons>>();
+        app.add_systems(Update, switch_listener::<CabinetButtons>);
+        app
+    }
+
+    #[test]
+    fn it_maps_switch_reports_to_inputs() {
+        let mut app = app();
+        app.world_mut().send_event(FastIoEvent::SwitchClosed {
+            id: 0x1a,
+            time: Duration::ZERO,
+        });
+        app.update();
+
+        let switches = app.world().resource::<ButtonInput<CabinetButtons>>();
+        assert!(switches.just_pressed(CabinetButtons::StartButton));
+        let events = app
+            .world()
+            .resource::<Events<SwitchInput<CabinetButtons>>>();
+        let sent = events.iter_current_update_events().collect::<Vec<_>>();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].state, SwitchState::Closed);
+    }
+
+    #[test]
+    fn it_seeds_inputs_from_switch_states() {
+        let mut app = app();
+        let mut states = vec![false; 32];
+        states[0x1a] = true;
+        app.world_mut()
+            .send_event(FastIoEvent::SwitchStates(states));
+        app.update();
+
+        let switches = app.world().resource::<ButtonInput<CabinetButtons>>();
+        assert!(switches.pressed(CabinetButtons::StartButton));
+        assert!(!switches.just_pressed(CabinetButtons::StartButton));
+        let events = app
+            .world()
+            .resource::<Events<SwitchInput<CabinetButtons>>>();
+        assert!(events.is_empty());
+
+        // Timed from when the dump was read
+        let seeded = app.world().resource::<Time<Real>>().elapsed();
+        let times = app.world().resource::<SwitchTimes<CabinetButtons>>();
+        assert_eq!(times.last_closed(CabinetButtons::StartButton), Some(seeded));
+    }
+
+    #[test]
+    fn it_times_switch_changes() {
+        let mut app = app();
+        let start = CabinetButtons::StartButton;
+        app.update();
+
+        // Timed from when the report was read, not when it's handled
+        let closed = app.world().resource::<Time<Real>>().elapsed();
+        app.world_mut().send_event(FastIoEvent::SwitchClosed {
+            id: 0x1a,
+            time: closed,
+        });
+        app.update();
+        let event = app
+            .world()
+            .resource::<Events<SwitchInput<CabinetButtons>>>()
+            .iter_current_update_events()
+            .next()
+            .cloned()
+            .unwrap();
+        assert_eq!(event.time, closed);
+
+        app.update();
+        app.update();
+        let times = app.world().resource::<SwitchTimes<CabinetButtons>>();
+        assert_eq!(times.last_closed(start), Some(closed));
+        assert_eq!(times.closed_for(start), Some(Duration::from_millis(300)));
+        assert_eq!(times.open_for(start), None);
+        assert!(times.closed_within(start, Duration::from_millis(300)));
+        assert!(!times.closed_within(start, Duration::from_millis(200)));
+        assert!(!times.opened_within(start, Duration::from_secs(1)));
+
+        app.update();
+        let opened = app.world().resource::<Time<Real>>().elapsed();
+        app.world_mut().send_event(FastIoEvent::SwitchOpened {
+            id: 0x1a,
+            time: opened + Duration::from_millis(100),
+        });
+        app.update();
+        let times = app.world().resource::<SwitchTimes<CabinetButtons>>();
+        assert_eq!(times.closed_for(start), None);
+        assert_eq!(times.open_for(start), Some(Duration::ZERO));
+        assert!(times.opened_within(start, Duration::ZERO));
+        assert_eq!(times.last_closed(start), Some(closed));
+    }
+}
@@ -0,0 +1,44 @@
+use serialport::SerialPort;
+use std::{
+    fmt::Debug,
+    io::{self, Read, Write},
+};
+
+/// A connection to a FAST bus, such as a serial port or the simulator
+pub trait Transport: Read + Write + Send + Debug {
+    /// Whether the other end is still there, e.g. the USB cable hasn't been pulled
+    fn is_connected(&self) -> bool;
+}
+
+impl Transport for Box<dyn SerialPort> {
+    fn is_connected(&self) -> bool {
+        self.bytes_to_read().is_ok()
+    }
+}
+
+/// Stands in for a bus the controller doesn't have, e.g. the EXP bus of a Nano. Writes are
+/// discarded and nothing is ever read.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoBus;
+
+impl Read for NoBus {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::TimedOut.into())
+    }
+}
+
+impl Write for NoBus {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for NoBus {
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,192 @@
+use bevy::{
+    prelude::*,
+    time::common_conditions::{on_real_timer, on_timer},
+};
+use std::time::Duration;
+
+use super::{
+    command::IoCommand, connection::FastConnection, parser::FastIoEvent, resources::IoNetPort,
+    serial::io_write,
+};
+
+/// Watchdog - Keeps the FAST watchdog from expiring while the game is running
+///
+/// The watchdog is refreshed from a fixed timestep system. If the game hangs or crashes
+/// the refreshes stop, the watchdog expires, and the hardware turns off every driver.
+/// The time remaining is asked for once per timeout, so an expiry is reported once the
+/// game is running again. Added by the `Neutron` plugin.
+pub struct Watchdog {
+    /// Time without a refresh before the hardware shuts off
+    pub timeout: Duration,
+}
+
+/// Fired when the hardware reports that the watchdog expired, once per expiry
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogExpired;
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    pub timeout: Duration,
+}
+
+impl Plugin for Watchdog {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(WatchdogConfig {
+            timeout: self.timeout,
+        });
+        app.add_event::<WatchdogExpired>();
+
+        // Refresh well before the timeout so a slow frame doesn't trip it
+        let refresh_duration = self.timeout / 2;
+        app.add_systems(OnEnter(FastConnection::Connected), refresh_watchdog);
+        app.add_systems(
+            FixedUpdate,
+            refresh_watchdog
+                .run_if(in_state(FastConnection::Connected))
+                .run_if(on_timer(refresh_duration)),
+        );
+        // Asked before the fixed timestep, so a refresh catching up after a hang can't
+        // restart the watchdog before the board reports that it expired
+        app.add_systems(
+            PreUpdate,
+            query_watchdog
+                .run_if(in_state(FastConnection::Connected))
+                .run_if(on_real_timer(self.timeout)),
+        );
+        app.add_systems(Update, watchdog_listener);
+    }
+}
+
+fn refresh_watchdog(config: Res<WatchdogConfig>, port: ResMut<IoNetPort>) {
+    let command = IoCommand::Watchdog(Some(config.timeout));
+    io_write(command.to_string(), &port);
+}
+
+fn query_watchdog(port: ResMut<IoNetPort>) {
+    io_write(IoCommand::Watchdog(None).to_string(), &port);
+}
+
+fn watchdog_listener(
+    mut ev_io: EventReader<FastIoEvent>,
+    mut ev: EventWriter<WatchdogExpired>,
+    mut expired: Local<bool>,
+) {
+    for event in ev_io.read() {
+        match event {
+            FastIoEvent::WatchdogRemaining(remaining) => {
+                // Stays expired until the next refresh, so only the first answer reports it
+                if remaining.is_zero() && !*expired {
+                    error!("FAST watchdog expired, drivers have been disabled");
+                    ev.write(WatchdogExpired);
+                }
+                *expired = remaining.is_zero();
+            }
+            // The timeout was refused, so the watchdog isn't protecting the drivers
+            FastIoEvent::Failed { command } if command == "WD" => {
+                error!("FAST watchdog could not be configured, check the watchdog timeout");
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fast::{mock_port::MockPort, serial::io_read, simulator::SimulatedNeutron};
+    use bevy::{ecs::system::RunSystemOnce, state::app::StatesPlugin, time::TimeUpdateStrategy};
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    fn app_with_port(port: IoNetPort) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin));
+        app.insert_state(FastConnection::Connected);
+        app.insert_resource(port);
+        app.add_event::<FastIoEvent>();
+        app.add_plugins(Watchdog {
+            timeout: Duration::from_millis(500),
+        });
+        app
+    }
+
+    fn mock_port(port: &MockPort) -> IoNetPort {
+        IoNetPort(Arc::new(Mutex::new(Box::new(port.clone()))))
+    }
+
+    #[test]
+    fn it_configures_the_watchdog_once_connected() {
+        let port = MockPort::default();
+        let mut app = app_with_port(mock_port(&port));
+        app.update();
+        assert_eq!(port.written(), vec!["WD:1F4"]);
+    }
+
+    #[test]
+    fn it_refreshes_the_watchdog() {
+        let port = MockPort::default();
+        let mut app = app_with_port(mock_port(&port));
+        app.world_mut().run_system_once(refresh_watchdog).unwrap();
+        app.world_mut().run_system_once(refresh_watchdog).unwrap();
+        assert_eq!(port.written(), vec!["WD:1F4", "WD:1F4"]);
+    }
+
+    #[test]
+    fn it_reports_when_the_watchdog_expires() {
+        let simulator = SimulatedNeutron::new(0);
+        let mut app = app_with_port(IoNetPort(Arc::new(Mutex::new(Box::new(
+            simulator.io_port(),
+        )))));
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            50,
+        )));
+        app.add_systems(FixedFirst, io_read);
+        let expired = |app: &App| {
+            let events = app.world().resource::<Events<WatchdogExpired>>();
+            events.iter_current_update_events().count()
+        };
+
+        // Refreshed in time, so every query finds time remaining
+        for _ in 0..40 {
+            app.update();
+            assert_eq!(expired(&app), 0);
+        }
+        let queries = simulator
+            .io_commands()
+            .iter()
+            .filter(|command| **command == IoCommand::Watchdog(None))
+            .count();
+        assert!(queries > 2);
+
+        // A hung game stops refreshing, and the watchdog runs out on the board before the
+        // next frame
+        thread::sleep(Duration::from_millis(600));
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            600,
+        )));
+        app.update();
+        let mut reports = expired(&app);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            50,
+        )));
+        for _ in 0..40 {
+            app.update();
+            reports += expired(&app);
+        }
+        assert_eq!(reports, 1);
+    }
+
+    #[test]
+    fn it_does_not_treat_a_refused_timeout_as_expiry() {
+        let port = MockPort::default();
+        let mut app = app_with_port(mock_port(&port));
+        app.add_systems(PreUpdate, io_read);
+        port.respond("WD:F\r");
+        app.update();
+
+        let events = app.world().resource::<Events<WatchdogExpired>>();
+        assert_eq!(events.iter_current_update_events().count(), 0);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod fast;
+pub mod pinball;
+pub mod rgb_led;
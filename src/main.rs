@@ -5,6 +5,7 @@ use bevy::animation::*;
 use bevy::color::palettes::tailwind::{TEAL_200, TEAL_400};
 use bevy::log::{Level, LogPlugin};
 use bevy::{color::palettes::css::*, prelude::*};
+use bevy_pin::{fast, pinball, rgb_led};
 use fast::{ExpansionBoard, ExpansionLeds, LedDefinition, Neutron};
 use pinball::dev_tools::keyboard::SwitchEmulator;
 use pinball::*;
@@ -14,9 +15,6 @@ use rgb_led::{
 };
 
 mod examples;
-mod fast;
-mod pinball;
-mod rgb_led;
 
 fn main() {
     let playfield_leds = (0..8)
@@ -38,11 +36,7 @@ fn main() {
     .add_plugins(PinballBase {
         led_brightness_scale: 0.66,
     })
-    .add_plugins(Neutron {
-        io_port_path: "COM5",
-        exp_port_path: "COM7",
-        ..Default::default()
-    })
+    .add_plugins(Neutron::default())
     .add_plugins(ExpansionLeds {
         leds: playfield_leds,
         ..Default::default()
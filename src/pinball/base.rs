@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use super::{CabinetButtons, CabinetSwitches, Inputs, LowerThirdsSwitches, MachineState};
+use super::{CabinetButtons, CabinetSwitches, Inputs, LowerThirdsSwitches, MachineState, Tilt};
 
 /// A plugin to setup all the base events and resources
 pub struct PinballBase {
@@ -23,6 +23,7 @@ impl Plugin for PinballBase {
         app.add_plugins(Inputs(LowerThirdsSwitches::default()));
 
         app.init_state::<MachineState>();
+        app.add_event::<Tilt>();
 
         app.insert_resource(PinballConfig {
             led_luminance_scale: self.led_brightness_scale,
@@ -1,4 +1,4 @@
-use std::hash::Hash;
+use std::{hash::Hash, time::Duration};
 
 use bevy::prelude::*;
 
@@ -19,4 +19,19 @@ pub struct PlayfieldPosition {
 #[derive(Component, Debug, Clone, PartialEq, Default, Reflect)]
 pub struct RgbLed {
     pub color: Srgba,
+    /// Time the hardware should take to fade to `color`. Zero jumps straight to it. Reset
+    /// to zero once sent, so a fade only applies to the color it was set with
+    pub fade: Duration,
+    pub transition_mode: TransitionMode,
+}
+
+/// How animations move an `RgbLed` between colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Reflect)]
+pub enum TransitionMode {
+    /// Every frame of an animation is rendered in software and sent to the LED
+    #[default]
+    Rendered,
+    /// Linear stages are handed to the hardware as a single fade, e.g. on FAST
+    /// expansion boards. Every other stage is still rendered in software.
+    HardwareFade,
 }
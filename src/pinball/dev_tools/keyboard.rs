@@ -2,7 +2,7 @@ use std::{collections::HashMap, hash::Hash};
 
 use bevy::prelude::*;
 
-use crate::pinball::{SwitchInput, SwitchState};
+use crate::pinball::{SwitchInput, SwitchState, SwitchTimes};
 
 #[allow(dead_code)]
 pub struct SwitchEmulator<T: Copy + Eq + Hash + Send + Sync + 'static>(pub HashMap<KeyCode, T>);
@@ -18,22 +18,27 @@ fn switch_emulator<T: Copy + Eq + Hash + Send + Sync + 'static>(
     mapping: Res<SwitchEmulatorMapping<T>>,
     keys: ResMut<ButtonInput<KeyCode>>,
     mut switches: ResMut<ButtonInput<T>>,
+    mut times: ResMut<SwitchTimes<T>>,
+    time: Res<Time<Real>>,
     mut ev: EventWriter<SwitchInput<T>>,
 ) {
     for (key, switch_id) in mapping.0.iter() {
-        if keys.just_pressed(*key) {
+        let state = if keys.just_pressed(*key) {
             switches.press(*switch_id);
-            ev.send(SwitchInput {
-                id: *switch_id,
-                state: SwitchState::Closed,
-            });
+            SwitchState::Closed
         } else if keys.just_released(*key) {
             switches.release(*switch_id);
-            ev.send(SwitchInput {
-                id: *switch_id,
-                state: SwitchState::Open,
-            });
-        }
+            SwitchState::Open
+        } else {
+            continue;
+        };
+        let input = SwitchInput {
+            id: *switch_id,
+            state,
+            time: time.elapsed(),
+        };
+        times.record(&input);
+        ev.write(input);
     }
 }
 
@@ -1,4 +1,4 @@
-use std::hash::Hash;
+use std::{hash::Hash, time::Duration};
 
 use bevy::prelude::*;
 
@@ -9,10 +9,16 @@ pub enum MachineState {
     InGame,
 }
 
+/// Fired when the player tilts the machine
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tilt;
+
 #[derive(Event, Debug, Clone)]
 pub struct SwitchInput<T: Copy + Eq + Hash + Send + Sync + 'static> {
     pub id: T,
     pub state: SwitchState,
+    /// `Time<Real>` elapsed when the change was read
+    pub time: Duration,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -1,8 +1,8 @@
-use std::hash::Hash;
+use std::{collections::HashMap, hash::Hash, time::Duration};
 
 use bevy::prelude::*;
 
-use super::SwitchInput;
+use super::{SwitchInput, SwitchState};
 
 /// A plugin to setup an input type (switch, button, etc.)
 pub struct Inputs<T: Copy + Eq + Hash + Send + Sync + 'static>(pub T);
@@ -11,5 +11,109 @@ impl<T: Copy + Eq + Hash + Send + Sync + 'static> Plugin for Inputs<T> {
     fn build(&self, app: &mut App) {
         app.init_resource::<ButtonInput<T>>();
         app.add_event::<SwitchInput<T>>();
+        SwitchTimes::<T>::init(app);
     }
 }
+
+/// SwitchTimes - When each input last closed and opened
+///
+/// Updated by whatever sends `SwitchInput<T>`, using the same `Time<Real>` elapsed as
+/// `SwitchInput::time`, so timings keep counting while the game is paused. An input has no
+/// times until it first changes.
+#[derive(Resource, Debug)]
+pub struct SwitchTimes<T: Copy + Eq + Hash + Send + Sync + 'static> {
+    now: Duration,
+    changes: HashMap<T, SwitchChanges>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SwitchChanges {
+    state: SwitchState,
+    last_closed: Option<Duration>,
+    last_opened: Option<Duration>,
+}
+
+impl<T: Copy + Eq + Hash + Send + Sync + 'static> Default for SwitchTimes<T> {
+    fn default() -> Self {
+        Self {
+            now: Duration::ZERO,
+            changes: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Copy + Eq + Hash + Send + Sync + 'static> SwitchTimes<T> {
+    /// Adds the resource and keeps its clock current, unless another plugin already has
+    pub fn init(app: &mut App) {
+        if app.world().contains_resource::<Self>() {
+            return;
+        }
+        app.init_resource::<Self>();
+        app.add_systems(First, update_switch_times::<T>);
+    }
+
+    /// Record a change sent as a `SwitchInput<T>`
+    pub fn record(&mut self, input: &SwitchInput<T>) {
+        let changes = self.changes.entry(input.id).or_insert(SwitchChanges {
+            state: input.state,
+            last_closed: None,
+            last_opened: None,
+        });
+        changes.state = input.state;
+        match input.state {
+            SwitchState::Closed => changes.last_closed = Some(input.time),
+            SwitchState::Open => changes.last_opened = Some(input.time),
+        }
+    }
+
+    /// When the input last closed
+    pub fn last_closed(&self, id: T) -> Option<Duration> {
+        self.changes.get(&id)?.last_closed
+    }
+
+    /// When the input last opened
+    pub fn last_opened(&self, id: T) -> Option<Duration> {
+        self.changes.get(&id)?.last_opened
+    }
+
+    /// How long the input has been closed, or `None` while it's open
+    pub fn closed_for(&self, id: T) -> Option<Duration> {
+        let changes = self.changes.get(&id)?;
+        match changes.state {
+            SwitchState::Closed => Some(self.since(changes.last_closed?)),
+            SwitchState::Open => None,
+        }
+    }
+
+    /// How long the input has been open, or `None` while it's closed
+    pub fn open_for(&self, id: T) -> Option<Duration> {
+        let changes = self.changes.get(&id)?;
+        match changes.state {
+            SwitchState::Open => Some(self.since(changes.last_opened?)),
+            SwitchState::Closed => None,
+        }
+    }
+
+    /// Whether the input closed within the last `duration`, even if it has opened since
+    pub fn closed_within(&self, id: T, duration: Duration) -> bool {
+        self.last_closed(id)
+            .is_some_and(|time| self.since(time) <= duration)
+    }
+
+    /// Whether the input opened within the last `duration`, even if it has closed since
+    pub fn opened_within(&self, id: T, duration: Duration) -> bool {
+        self.last_opened(id)
+            .is_some_and(|time| self.since(time) <= duration)
+    }
+
+    fn since(&self, time: Duration) -> Duration {
+        self.now.saturating_sub(time)
+    }
+}
+
+fn update_switch_times<T: Copy + Eq + Hash + Send + Sync + 'static>(
+    time: Res<Time<Real>>,
+    mut times: ResMut<SwitchTimes<T>>,
+) {
+    times.now = time.elapsed();
+}
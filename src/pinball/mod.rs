@@ -8,5 +8,5 @@ pub mod payment;
 pub use base::*;
 pub use components::*;
 pub use global::*;
-pub use inputs::Inputs;
+pub use inputs::{Inputs, SwitchTimes};
 pub use payment::PaymentPlugin;
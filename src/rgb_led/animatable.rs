@@ -6,7 +6,7 @@ use crate::pinball::RgbLed;
 
 use super::{
     animation::{Animation, AnimationPlayback, PlaybackState, PlaybackType},
-    applicator::{AnimationApplicator, RgbLedColorApplicator},
+    applicator::{AnimationApplicator, AnimationOffload, RgbLedColorApplicator, RgbLedFadeOffload},
 };
 
 #[derive(Component)]
@@ -14,6 +14,7 @@ pub struct Animatable<T: Send + Sync, C: Component> {
     queue: VecDeque<AnimationPlayback<T, C>>,
     state: PlaybackState,
     applicator: AnimationApplicator<T, C>,
+    offload: Option<AnimationOffload<T, C>>,
 }
 
 impl<T, C> Animatable<T, C>
@@ -26,9 +27,16 @@ where
             queue: VecDeque::default(),
             state: PlaybackState::Inactive,
             applicator,
+            offload: None,
         }
     }
 
+    /// Try handing each stage off with the given function before rendering it
+    pub fn with_offload(mut self, offload: AnimationOffload<T, C>) -> Self {
+        self.offload = Some(offload);
+        self
+    }
+
     pub fn enqueue(&mut self, anim: Animation<T>) {
         self.queue.push_back(self.anim_to_playback(anim));
     }
@@ -56,6 +64,8 @@ where
             current_stage: 0,
             play_count: 0,
             applicator: self.applicator,
+            offload: self.offload,
+            offloaded: None,
         }
     }
 
@@ -76,7 +86,7 @@ where
 
 impl Animatable<Srgba, RgbLed> {
     pub fn color() -> Self {
-        Self::new(RgbLedColorApplicator)
+        Self::new(RgbLedColorApplicator).with_offload(RgbLedFadeOffload)
     }
 }
 
@@ -90,16 +100,19 @@ pub fn render_all_animatable<T: Send + Sync + 'static, C: Component<Mutability =
     }
 }
 
+/// Only marks the component as changed when something is actually applied to it, so an
+/// idle or offloaded animation doesn't resend the same value every frame
 fn render_animatable<T: Send + Sync + 'static, C: Component>(
     anim: &mut Animatable<T, C>,
     delta: Duration,
-    component: &mut C,
+    component: &mut Mut<C>,
 ) {
     if anim.state == PlaybackState::Active {
         if let Some(playback) = anim.queue.front_mut() {
             playback.timer.tick(delta);
             if playback.timer.just_finished() {
                 playback.current_stage += 1;
+                playback.offloaded = None;
 
                 // End of animation
                 if playback.current_stage == playback.animation.stages.len() {
@@ -137,9 +150,16 @@ fn render_animatable<T: Send + Sync + 'static, C: Component>(
             // Render current value
             match playback.animation.stages.get(playback.current_stage) {
                 Some(stage) => {
-                    let phase =
-                        playback.timer.elapsed_secs() / playback.timer.duration().as_secs_f32();
-                    (playback.applicator)(phase, stage, component);
+                    let offload = playback.offload;
+                    let offloaded = *playback.offloaded.get_or_insert_with(|| match offload {
+                        Some(offload) => offload(stage, component.as_mut()),
+                        None => false,
+                    });
+                    if !offloaded {
+                        let phase =
+                            playback.timer.elapsed_secs() / playback.timer.duration().as_secs_f32();
+                        (playback.applicator)(phase, stage, component.as_mut());
+                    }
                 }
                 None => {}
             }
@@ -2,7 +2,10 @@ use std::{fmt::Debug, slice::Iter, time::Duration};
 
 use bevy::prelude::*;
 
-use super::{applicator::AnimationApplicator, curve::Curve};
+use super::{
+    applicator::{AnimationApplicator, AnimationOffload},
+    curve::Curve,
+};
 
 #[derive(Debug, Default, Clone)]
 pub struct AnimationStage<T: Send + Sync> {
@@ -190,6 +193,9 @@ pub struct AnimationPlayback<T: Send + Sync, C: Component> {
     pub current_stage: usize,
     pub play_count: usize,
     pub applicator: AnimationApplicator<T, C>,
+    pub offload: Option<AnimationOffload<T, C>>,
+    /// Whether the current stage was offloaded, or `None` until the stage starts
+    pub offloaded: Option<bool>,
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
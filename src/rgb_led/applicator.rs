@@ -1,15 +1,40 @@
-use bevy::{color::Srgba, ecs::component::Component};
+use bevy::{
+    color::{ColorToPacked, Srgba},
+    ecs::component::Component,
+};
 
-use crate::pinball::RgbLed;
+use std::time::Duration;
 
-use super::animation::AnimationStage;
+use crate::pinball::{RgbLed, TransitionMode};
+
+use super::{animation::AnimationStage, curve::Curve};
 
 pub type AnimationApplicator<T, C: Component> = fn(f32, &AnimationStage<T>, &mut C);
 
+/// Called once at the start of each stage. Returns `true` if the whole stage was handed
+/// off, e.g. to the hardware, in which case the applicator isn't called for that stage.
+pub type AnimationOffload<T, C> = fn(&AnimationStage<T>, &mut C) -> bool;
+
 pub const RgbLedColorApplicator: AnimationApplicator<Srgba, RgbLed> =
     |phase: f32, stage: &AnimationStage<Srgba>, component: &mut RgbLed| {
         let stage_phase = stage.curve.sample(phase);
         component.color = blend_srgba(stage.from, stage.to, stage_phase);
+        component.fade = Duration::ZERO;
+    };
+
+/// Hands linear stages to LEDs which fade in hardware. The hardware fades from whatever
+/// it is showing, so a stage which doesn't start at the current color is still rendered.
+#[allow(non_upper_case_globals)]
+pub const RgbLedFadeOffload: AnimationOffload<Srgba, RgbLed> =
+    |stage: &AnimationStage<Srgba>, component: &mut RgbLed| {
+        let offload = component.transition_mode == TransitionMode::HardwareFade
+            && matches!(stage.curve, Curve::Linear)
+            && component.color.to_u8_array_no_alpha() == stage.from.to_u8_array_no_alpha();
+        if offload {
+            component.color = stage.to;
+            component.fade = stage.duration;
+        }
+        offload
     };
 
 pub fn blend_f32(a: f32, b: f32, phase: f32) -> f32 {
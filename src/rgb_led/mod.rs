@@ -8,8 +8,9 @@ mod math;
 mod plugin;
 mod sequence;
 
-pub use animatable::Animatable;
+pub use animatable::{Animatable, render_all_animatable};
 pub use animation::*;
+pub use applicator::{AnimationApplicator, blend_f32};
 pub use curve::Curve;
 pub use flasher::*;
 pub use gradient::*;